@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context};
+use cookie_store::{CookieDomain, CookieExpiration, CookieStore, RawCookie};
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use time::OffsetDateTime;
+
+#[derive(Debug, Default)]
+pub struct CookieJar(Mutex<CookieStore>);
+
+impl CookieJar {
+    pub fn load(path: &Path) -> anyhow::Result<CookieJar> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(CookieJar::default()),
+            Err(e) => {
+                return Err(e).context(format!("fail to open cookie jar: {}", path.display()))
+            }
+        };
+        let mut store = CookieStore::default();
+        for (i, line) in text.lines().enumerate() {
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed =
+                || anyhow!("malformed cookie jar at line {}: {}", i + 1, path.display());
+            let [domain, subdomains, cookie_path, secure, expires, name, value] =
+                line.split('\t').collect::<Vec<_>>()[..]
+            else {
+                return Err(malformed());
+            };
+            let host = domain.trim_start_matches('.');
+            let secure = secure == "TRUE";
+            let scheme = if secure { "https" } else { "http" };
+            let url = Url::parse(&format!("{}://{}{}", scheme, host, cookie_path))
+                .map_err(|_| malformed())?;
+            let mut cookie = format!("{}={}; Path={}", name, value, cookie_path);
+            if subdomains == "TRUE" {
+                write!(cookie, "; Domain={}", host)?;
+            }
+            if secure {
+                cookie.push_str("; Secure");
+            }
+            if http_only {
+                cookie.push_str("; HttpOnly");
+            }
+            let mut cookie = RawCookie::parse(cookie).map_err(|_| malformed())?;
+            let expires = expires.parse::<i64>().map_err(|_| malformed())?;
+            if expires > 0 {
+                let at = OffsetDateTime::from_unix_timestamp(expires).map_err(|_| malformed())?;
+                cookie.set_expires(at);
+            }
+            // expired cookies are rejected by the store and dropped here
+            let _ = store.insert_raw(&cookie, &url);
+        }
+        Ok(CookieJar(Mutex::new(store)))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let store = self.0.lock().unwrap();
+        let mut out = String::from("# Netscape HTTP Cookie File\n");
+        for cookie in store.iter_unexpired() {
+            let (domain, subdomains) = match cookie.domain {
+                CookieDomain::HostOnly(ref d) => (d.clone(), "FALSE"),
+                CookieDomain::Suffix(ref d) => (format!(".{}", d), "TRUE"),
+                _ => continue,
+            };
+            let expires = match cookie.expires {
+                CookieExpiration::AtUtc(ref at) => at.unix_timestamp(),
+                CookieExpiration::SessionEnd => 0,
+            };
+            let secure = if cookie.secure().unwrap_or(false) {
+                "TRUE"
+            } else {
+                "FALSE"
+            };
+            if cookie.http_only().unwrap_or(false) {
+                out.push_str("#HttpOnly_");
+            }
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                domain,
+                subdomains,
+                String::from(&cookie.path),
+                secure,
+                expires,
+                cookie.name(),
+                cookie.value()
+            )?;
+        }
+        fs::write(path, out).context(format!("fail to write cookie jar: {}", path.display()))
+    }
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|s| RawCookie::parse(s.to_string()).ok());
+        self.0.lock().unwrap().store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let values: Vec<String> = self
+            .0
+            .lock()
+            .unwrap()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&values.join("; ")).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore as _;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_load_and_save() {
+        let path = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::write(
+            &path,
+            "# Netscape HTTP Cookie File\n\
+             .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n\
+             #HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t4102444800\ttoken\txyz\n\
+             old.example.org\tFALSE\t/\tFALSE\t1\texpired\tgone\n",
+        )
+        .unwrap();
+
+        let jar = CookieJar::load(&path).unwrap();
+        let url = |s: &str| Url::parse(s).unwrap();
+        let sent = jar.cookies(&url("https://api.example.com/v1/me")).unwrap();
+        let mut sent: Vec<&str> = sent.to_str().unwrap().split("; ").collect();
+        sent.sort();
+        assert_eq!(sent, vec!["session=abc", "token=xyz"]);
+        assert_eq!(
+            jar.cookies(&url("http://www.example.com/")).unwrap(),
+            "session=abc"
+        );
+        assert!(jar.cookies(&url("http://old.example.org/")).is_none());
+
+        jar.save(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut lines: Vec<&str> = saved.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "# Netscape HTTP Cookie File",
+                "#HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t4102444800\ttoken\txyz",
+                ".example.com\tTRUE\t/\tFALSE\t0\tsession\tabc",
+            ]
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::LazyLock;
 use regex::{Match, Regex};
 use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 
@@ -8,6 +9,27 @@ use std::fmt;
 pub enum InterpError {
     ValueNotFound(String),
     CircularReference(String),
+    FileNotRead(String, String),
+    At(String, Box<InterpError>),
+}
+
+impl InterpError {
+    pub fn within(self, segment: &str) -> Self {
+        match self {
+            InterpError::At(path, e) if path.starts_with('[') => {
+                InterpError::At(format!("{}{}", segment, path), e)
+            }
+            InterpError::At(path, e) => InterpError::At(format!("{}.{}", segment, path), e),
+            e => InterpError::At(segment.to_string(), Box::new(e)),
+        }
+    }
+
+    pub fn root(&self) -> &InterpError {
+        match self {
+            InterpError::At(_, e) => e.root(),
+            e => e,
+        }
+    }
 }
 
 impl fmt::Display for InterpError {
@@ -15,33 +37,100 @@ impl fmt::Display for InterpError {
         match self {
             InterpError::ValueNotFound(s) => write!(f, "value named \"{}\" not defined", s),
             InterpError::CircularReference(s) => write!(f, "found circular reference in \"{}\"", s),
+            InterpError::FileNotRead(p, e) => write!(f, "fail to read file: {}: {}", p, e),
+            InterpError::At(path, e) => write!(f, "at {}: {}", path, e),
         }
     }
 }
 impl std::error::Error for InterpError {}
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InterpWarning {
+    pub name: String,
+    pub default: String,
+}
+
+impl fmt::Display for InterpWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "value named \"{}\" not defined, default \"{}\" is used",
+            self.name, self.default
+        )
+    }
+}
+
+type Warnings = RefCell<Vec<InterpWarning>>;
+
 pub type InterpResult<T> = Result<T, InterpError>;
-pub struct InterpContext(BTreeMap<String, String>);
+pub struct InterpContext {
+    values: BTreeMap<String, (String, Vec<InterpWarning>)>,
+    warnings: Warnings,
+}
 
 pub fn create_interpolation_context(map: BTreeMap<String, String>) -> InterpResult<InterpContext> {
     let mut cache = HashMap::new();
-    Ok(InterpContext(
-        map.iter()
-            .map(|(k, v)| {
-                Ok((
-                    k.clone(),
-                    interpolate_with_func(v, &mut |key| getter_with_cache(key, &map, &mut cache))?
-                        .to_string(),
-                ))
-            })
-            .collect::<InterpResult<_>>()?,
-    ))
+    let values = map
+        .keys()
+        .map(|k| {
+            let warnings = RefCell::new(vec![]);
+            let v = getter_with_cache(k, &map, &mut cache, &warnings)?.to_string();
+            Ok((k.clone(), (v, warnings.into_inner())))
+        })
+        .collect::<InterpResult<_>>()?;
+    Ok(InterpContext {
+        values,
+        warnings: RefCell::new(vec![]),
+    })
+}
+
+impl InterpContext {
+    pub fn take_warnings(&self) -> Vec<InterpWarning> {
+        let mut warnings = self.warnings.take();
+        warnings.sort();
+        warnings.dedup();
+        warnings
+    }
 }
 
 static PLACEHOLDER_PATTERN: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(\$)?\$(?:\{([^}]+)\}|([[:alnum:]]+))").unwrap());
 
-fn interpolate_with_func<'i, F>(s: &'i str, getter: &mut F) -> InterpResult<Cow<'i, str>>
+#[derive(Debug, Clone, Copy)]
+enum CaseOp {
+    Upper,
+    Lower,
+}
+
+impl CaseOp {
+    fn apply(self, s: &str) -> String {
+        match self {
+            CaseOp::Upper => s.to_uppercase(),
+            CaseOp::Lower => s.to_lowercase(),
+        }
+    }
+}
+
+fn split_key(key: &str) -> (&str, Option<CaseOp>, Option<&str>) {
+    let (name, default) = match key.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (key, None),
+    };
+    if let Some(name) = name.strip_suffix("^^") {
+        (name, Some(CaseOp::Upper), default)
+    } else if let Some(name) = name.strip_suffix(",,") {
+        (name, Some(CaseOp::Lower), default)
+    } else {
+        (name, None, default)
+    }
+}
+
+fn interpolate_with_func<'i, F>(
+    s: &'i str,
+    getter: &mut F,
+    warnings: &Warnings,
+    bare_names: bool,
+) -> InterpResult<Cow<'i, str>>
 where
     F: FnMut(&str) -> InterpResult<Cow<'i, str>>,
 {
@@ -50,10 +139,30 @@ where
     for cap in PLACEHOLDER_PATTERN.captures_iter(s) {
         let m: Match = cap.get(0).unwrap();
         vec.push(Cow::from(&s[ix..m.start()]));
-        if cap.get(1).is_some() {
+        if cap.get(3).is_some() && !bare_names {
+            vec.push(Cow::from(m.as_str()));
+        } else if cap.get(1).is_some() {
             vec.push(Cow::from(&s[m.start() + 1..m.end()]));
         } else if let Some(key) = cap.get(2) {
-            vec.push(getter(key.as_str())?);
+            let (name, op, default) = split_key(key.as_str());
+            let value = match env_value(name) {
+                Some(v) => Ok(Cow::from(v)),
+                None => getter(name),
+            };
+            let value = match (value, default) {
+                (Err(InterpError::ValueNotFound(ref k)), Some(default)) if k == name => {
+                    warnings.borrow_mut().push(InterpWarning {
+                        name: name.to_string(),
+                        default: default.to_string(),
+                    });
+                    Cow::from(default)
+                }
+                (v, _) => v?,
+            };
+            match op {
+                Some(op) => vec.push(Cow::from(op.apply(&value))),
+                None => vec.push(value),
+            }
         } else if let Some(key) = cap.get(3) {
             vec.push(getter(key.as_str())?);
         }
@@ -67,14 +176,79 @@ where
     }
 }
 
+fn env_value(key: &str) -> Option<String> {
+    std::env::var(key.strip_prefix("env:")?).ok()
+}
+
+pub fn placeholders(s: &str) -> Vec<&str> {
+    PLACEHOLDER_PATTERN
+        .captures_iter(s)
+        .filter(|cap| cap.get(1).is_none())
+        .filter_map(|cap| cap.get(2).or_else(|| cap.get(3)))
+        .map(|m| split_key(m.as_str()).0)
+        .collect()
+}
+
+pub fn braced_placeholders(s: &str) -> Vec<&str> {
+    PLACEHOLDER_PATTERN
+        .captures_iter(s)
+        .filter(|cap| cap.get(1).is_none())
+        .filter_map(|cap| cap.get(2))
+        .map(|m| split_key(m.as_str()).0)
+        .collect()
+}
+
+pub fn is_resolvable(key: &str, map: &BTreeMap<String, String>) -> bool {
+    env_value(key).is_some()
+        || getter_with_cache(key, map, &mut HashMap::new(), &RefCell::new(vec![])).is_ok()
+}
+
 pub fn interpolate<'i, T>(s: &'i str, ctxt: &'i InterpContext) -> InterpResult<T>
 where
     T: From<Cow<'i, str>>,
 {
-    interpolate_with_func(s, &mut |key| match ctxt.0.get(key) {
-        Some(s) => Ok(Cow::from(s)),
-        None => Err(InterpError::ValueNotFound(key.to_string())),
-    })
+    interpolate_in_context(s, ctxt, true)
+}
+
+pub fn interpolate_lazily(s: &str, map: &BTreeMap<String, String>) -> InterpResult<String> {
+    let mut cache = HashMap::new();
+    let warnings = RefCell::new(vec![]);
+    interpolate_with_func(
+        s,
+        &mut |k| getter_with_cache(k, map, &mut cache, &warnings),
+        &warnings,
+        true,
+    )
+    .map(|c| c.into_owned())
+}
+
+pub fn interpolate_braced<'i, T>(s: &'i str, ctxt: &'i InterpContext) -> InterpResult<T>
+where
+    T: From<Cow<'i, str>>,
+{
+    interpolate_in_context(s, ctxt, false)
+}
+
+fn interpolate_in_context<'i, T>(
+    s: &'i str,
+    ctxt: &'i InterpContext,
+    bare_names: bool,
+) -> InterpResult<T>
+where
+    T: From<Cow<'i, str>>,
+{
+    interpolate_with_func(
+        s,
+        &mut |key| match ctxt.values.get(key) {
+            Some((s, warnings)) => {
+                ctxt.warnings.borrow_mut().extend(warnings.iter().cloned());
+                Ok(Cow::from(s))
+            }
+            None => Err(InterpError::ValueNotFound(key.to_string())),
+        },
+        &ctxt.warnings,
+        bare_names,
+    )
     .map(|c| c.into())
 }
 
@@ -86,17 +260,28 @@ enum Delay<T> {
 fn getter_with_cache<'i>(
     key: &str,
     map: &'i BTreeMap<String, String>,
-    cache: &mut HashMap<String, Delay<String>>,
+    cache: &mut HashMap<String, Delay<(String, Vec<InterpWarning>)>>,
+    warnings: &Warnings,
 ) -> InterpResult<Cow<'i, str>> {
     match cache.get(key) {
         Some(Delay::Pending) => Err(InterpError::CircularReference(key.to_string())),
-        Some(Delay::Done(s)) => Ok(Cow::from(s.clone())),
+        Some(Delay::Done((s, w))) => {
+            warnings.borrow_mut().extend(w.iter().cloned());
+            Ok(Cow::from(s.clone()))
+        }
         None => {
             if map.contains_key(key) {
                 cache.insert(key.to_string(), Delay::Pending);
-                let s =
-                    interpolate_with_func(&map[key], &mut |k| getter_with_cache(k, map, cache))?;
-                cache.insert(key.to_string(), Delay::Done(s.to_string()));
+                let inner = RefCell::new(vec![]);
+                let s = interpolate_with_func(
+                    &map[key],
+                    &mut |k| getter_with_cache(k, map, cache, &inner),
+                    &inner,
+                    true,
+                )?;
+                let inner = inner.into_inner();
+                warnings.borrow_mut().extend(inner.iter().cloned());
+                cache.insert(key.to_string(), Delay::Done((s.to_string(), inner)));
                 Ok(s)
             } else {
                 Err(InterpError::ValueNotFound(key.to_string()))
@@ -137,4 +322,162 @@ mod tests {
             )),
         );
     }
+
+    #[test]
+    fn test_escape_dollar_bare_name() {
+        let mut ctxt = BTreeMap::new();
+        ctxt.insert("FOO".into(), "foo".into());
+        ctxt.insert("BAR".into(), "bar".into());
+        ctxt.insert("ESCAPED".into(), "$$FOO".into());
+        let ctxt = create_interpolation_context(ctxt).unwrap();
+        let cases = [
+            ("$$FOO", "$FOO"),
+            ("$$FOO${BAR}", "$FOObar"),
+            ("$${FOO}$BAR", "${FOO}bar"),
+            ("$FOO$$BAR", "foo$BAR"),
+            ("$$FOO$$BAR", "$FOO$BAR"),
+            ("${FOO}$${BAR}$$FOO", "foo${BAR}$FOO"),
+            ("$$UNDEFINED", "$UNDEFINED"),
+            ("$${UNDEFINED}", "${UNDEFINED}"),
+            ("${ESCAPED}", "$FOO"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                interpolate(input, &ctxt),
+                Ok(String::from(expected)),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders("${scheme}://$host/${path} $${escaped}"),
+            vec!["scheme", "host", "path"],
+        );
+    }
+
+    #[test]
+    fn test_is_resolvable() {
+        let mut map = BTreeMap::new();
+        map.insert("foo".into(), "${bar}".into());
+        map.insert("bar".into(), "BAR".into());
+        map.insert("baz".into(), "${undefined}".into());
+        assert!(is_resolvable("foo", &map));
+        assert!(!is_resolvable("baz", &map));
+        assert!(!is_resolvable("undefined", &map));
+    }
+
+    #[test]
+    fn test_default_value() {
+        let mut map = BTreeMap::new();
+        map.insert("host".into(), "example.com".into());
+        map.insert("port".into(), "${PORT:-8080}".into());
+        let ctxt = create_interpolation_context(map).unwrap();
+        assert_eq!(
+            interpolate("${host:-localhost}/${path:-index.html}", &ctxt),
+            Ok(String::from("example.com/index.html")),
+        );
+        assert_eq!(
+            ctxt.take_warnings(),
+            vec![InterpWarning {
+                name: "path".into(),
+                default: "index.html".into()
+            }],
+        );
+        assert_eq!(interpolate("${port}", &ctxt), Ok(String::from("8080")));
+        assert_eq!(
+            ctxt.take_warnings(),
+            vec![InterpWarning {
+                name: "PORT".into(),
+                default: "8080".into()
+            }],
+        );
+        assert_eq!(
+            interpolate::<String>("${undefined}", &ctxt),
+            Err(InterpError::ValueNotFound("undefined".into())),
+        );
+        assert_eq!(placeholders("${path:-index.html}"), vec!["path"]);
+    }
+
+    #[test]
+    fn test_case_operator() {
+        let mut map = BTreeMap::new();
+        map.insert("name".into(), "World".into());
+        map.insert("shout".into(), "${name^^}!".into());
+        let ctxt = create_interpolation_context(map).unwrap();
+        assert_eq!(
+            interpolate("${name^^} ${name,,} ${shout}", &ctxt),
+            Ok(String::from("WORLD world WORLD!")),
+        );
+        assert_eq!(
+            interpolate("${undefined^^:-Default} ${name,,:-Default}", &ctxt),
+            Ok(String::from("DEFAULT world")),
+        );
+        assert_eq!(
+            interpolate("$${name^^} $${name,,}", &ctxt),
+            Ok(String::from("${name^^} ${name,,}")),
+        );
+        assert_eq!(
+            interpolate::<String>("${undefined,,}", &ctxt),
+            Err(InterpError::ValueNotFound("undefined".into())),
+        );
+        assert_eq!(
+            placeholders("${name^^} ${path,,:-index.html}"),
+            vec!["name", "path"]
+        );
+    }
+
+    #[test]
+    fn test_env_value() {
+        let _lock = crate::ENV_LOCK.lock().unwrap();
+        std::env::set_var("REQ_TEST_ENV_VALUE", "from-env");
+        std::env::remove_var("REQ_TEST_ENV_UNSET");
+        let mut map = BTreeMap::new();
+        map.insert("name".into(), "world".into());
+        map.insert("nested".into(), "${env:REQ_TEST_ENV_VALUE}".into());
+        let ctxt = create_interpolation_context(map.clone()).unwrap();
+        assert_eq!(
+            interpolate("${env:REQ_TEST_ENV_VALUE}, ${name}, ${nested}", &ctxt),
+            Ok(String::from("from-env, world, from-env")),
+        );
+        assert_eq!(
+            interpolate("$${env:REQ_TEST_ENV_VALUE}", &ctxt),
+            Ok(String::from("${env:REQ_TEST_ENV_VALUE}")),
+        );
+        assert_eq!(
+            interpolate::<String>("${env:REQ_TEST_ENV_UNSET}", &ctxt),
+            Err(InterpError::ValueNotFound("env:REQ_TEST_ENV_UNSET".into())),
+        );
+        assert_eq!(
+            interpolate("${env:REQ_TEST_ENV_UNSET:-fallback}", &ctxt),
+            Ok(String::from("fallback")),
+        );
+        assert!(is_resolvable("env:REQ_TEST_ENV_VALUE", &map));
+        assert!(!is_resolvable("env:REQ_TEST_ENV_UNSET", &map));
+        std::env::remove_var("REQ_TEST_ENV_VALUE");
+    }
+
+    #[test]
+    fn test_interpolate_braced() {
+        let mut map = BTreeMap::new();
+        map.insert("login".into(), "octocat".into());
+        map.insert("name".into(), "$login".into());
+        let ctxt = create_interpolation_context(map).unwrap();
+        assert_eq!(
+            interpolate_braced(
+                "($login: String = \"${login}\") $$x ${name} $${login}",
+                &ctxt
+            ),
+            Ok(String::from(
+                "($login: String = \"octocat\") $$x octocat ${login}"
+            )),
+        );
+        assert_eq!(
+            braced_placeholders("query($login: String!) { user(login: \"${LOGIN}\") }"),
+            vec!["LOGIN"],
+        );
+    }
 }
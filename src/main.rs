@@ -1,17 +1,29 @@
 #[macro_use]
 extern crate serde_derive;
 
+mod cookie_jar;
 mod data;
 mod interpolation;
+mod pinned_certs;
+mod progress;
+mod template;
 
 use anyhow::{anyhow, Context};
 use clap::Parser;
-use data::Req;
+use cookie_jar::CookieJar;
+use data::{Req, ReqDump, ReqFormat, ReqRetryJitter, ReqTask};
 use indicatif::{ProgressBar, ProgressStyle};
+use interpolation::InterpError;
+use std::borrow::Cow;
 use std::error::Error;
+use std::fmt;
 use std::fs;
-use std::io::{stdin, stdout, BufWriter, Read, Write};
-use std::process::ExitCode;
+use std::io::{stderr, stdin, stdout, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 enum ParseKVError<T, U>
@@ -72,8 +84,11 @@ where
 #[derive(Debug, Parser)]
 #[command(name = "req", about, version)]
 struct Opt {
-    #[arg(help = "Specify task by name")]
-    name: Option<String>,
+    #[arg(env = "REQ_TASK", help = "Specify tasks by name")]
+    name: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 
     #[arg(
         name = "DEF",
@@ -84,14 +99,31 @@ struct Opt {
     )]
     input: String,
 
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Parse <DEF> as toml, yaml or json, instead of guessing from its extension"
+    )]
+    format: Option<ReqFormat>,
+
     #[arg(
         name = "OUTPUT",
         short,
         long = "out",
-        help = "Write result to <OUTPUT>"
+        help = "Write result to <OUTPUT>, creating its parent directories"
     )]
     output: Option<String>,
 
+    #[arg(long, help = "Fail if parent directories of <OUTPUT> do not exist")]
+    no_create_dirs: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write status, headers, url and elapsed time of response to <PATH> as JSON"
+    )]
+    meta_out: Option<String>,
+
     #[arg(
         short,
         long = "include-header",
@@ -99,6 +131,43 @@ struct Opt {
     )]
     include_header: bool,
 
+    #[arg(
+        long,
+        help = "Write no response body but the status to stderr on non-success status"
+    )]
+    fail: bool,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Fail unless the response has header <NAME>"
+    )]
+    require_header: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "exec_command",
+        help = "Fail if response body differs from baseline <FILE>, which is saved if missing"
+    )]
+    snapshot: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATHS",
+        value_delimiter = ',',
+        requires = "snapshot",
+        help = "Ignore comma separated JSON paths like .meta.timestamp in --snapshot"
+    )]
+    diff_ignore: Vec<String>,
+
+    #[arg(
+        long,
+        requires = "snapshot",
+        help = "Ignore differences of whitespace in non-JSON bodies in --snapshot"
+    )]
+    ignore_whitespace: bool,
+
     #[arg(
         name = "KEY=VALUE",
         short = 'v',
@@ -108,179 +177,5122 @@ struct Opt {
     )]
     variables: Vec<(String, String)>,
 
+    #[arg(
+        long,
+        value_name = "KEY=VALUE",
+        help = "Pass variable in the form KEY=VALUE, taking precedence over all other sources",
+        value_parser = parse_key_val::<String, String>,
+    )]
+    env_override: Vec<(String, String)>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        help = "Read variables from <PATH> in the form KEY=VALUE, instead of config.env-file"
+    )]
+    env_file: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "env_file",
+        help = "Do not read variables from config.env-file"
+    )]
+    no_env_file: bool,
+
+    #[arg(
+        long,
+        help = "Print name, description, method and URL of tasks as JSON instead of sending request"
+    )]
+    list_json: bool,
+
     #[arg(long, help = "Print compatible curl command (experimental)")]
     curl: bool,
 
     #[arg(
         long,
-        help = "Dump internal structure of specified task without sending request"
+        help = "Narrate where the task, its variables and config come from, and print the request without sending it"
+    )]
+    explain: bool,
+
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "debug",
+        help = "Dump internal structure of specified task, or the raw request with --dryrun=http, without sending request"
+    )]
+    dryrun: Option<DryrunFormat>,
+
+    #[arg(
+        long,
+        alias = "print-resolved-url",
+        help = "Print URL of specified task with queries without sending request"
+    )]
+    print_url: bool,
+
+    #[arg(
+        long,
+        help = "List variables referenced by specified task without sending request"
+    )]
+    list_vars_used: bool,
+
+    #[arg(
+        long,
+        help = "Send a generated request id header and print it to stderr"
+    )]
+    request_id: bool,
+
+    #[arg(
+        long,
+        help = "Send only headers declared in the task, without default User-Agent"
+    )]
+    no_default_headers: bool,
+
+    #[arg(long, help = "Warn when a default value of placeholder is used")]
+    warn_vars: bool,
+
+    #[arg(
+        long,
+        alias = "fail-on-interpolation-warning",
+        help = "Fail when a default value of placeholder is used"
+    )]
+    strict_vars: bool,
+
+    #[arg(long, help = "Prompt for values of undefined variables")]
+    interactive: bool,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Send the request <REPEAT> times"
+    )]
+    repeat: u64,
+
+    #[arg(
+        long,
+        help = "Print counts, latency and bytes of the requests to stderr at the end"
+    )]
+    summary: bool,
+
+    #[arg(
+        long,
+        help = "Print iteration, status and latency of each request as a JSON line instead of the body"
+    )]
+    json_lines: bool,
+
+    #[arg(long, help = "Print each followed redirect hop to stderr")]
+    trace_redirects: bool,
+
+    #[arg(
+        long,
+        help = "Print headers of the request and the response to stderr, like curl -v"
+    )]
+    verbose: bool,
+
+    #[arg(
+        long,
+        alias = "redact-auth",
+        help = "Hide values of credential headers in --curl and --verbose output"
+    )]
+    redact: bool,
+
+    #[arg(
+        short,
+        long,
+        alias = "no-progress",
+        help = "Do not show progress bars of uploading and downloading"
+    )]
+    quiet: bool,
+
+    #[arg(
+        name = "NAME=VALUE",
+        long = "data-urlencode",
+        help = "Add urlencoded form field in the form NAME=VALUE to the body",
+        value_parser = parse_key_val::<String, String>,
+    )]
+    form_fields: Vec<(String, String)>,
+
+    #[arg(
+        name = "NAME=PATH",
+        long = "capture",
+        help = "Print the value at JSON <PATH> of the response as NAME=VALUE instead of the body",
+        value_parser = parse_key_val::<String, String>,
+    )]
+    captures: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with = "NAME=PATH",
+        help = "Print a line formatted from the response instead of the body, e.g. '{{status}} {{json .id}}'"
+    )]
+    template: Option<String>,
+
+    #[arg(
+        long,
+        requires = "template",
+        help = "Fail if a header or a JSONPath in --template matches nothing, instead of rendering empty"
+    )]
+    strict_template: bool,
+
+    #[arg(
+        name = "SIZE",
+        long = "max-filesize",
+        alias = "max-size",
+        help = "Abort download when response body exceeds <SIZE> (e.g. 100MB)",
+        value_parser = |s: &str| data::parse_size(s).map_err(|e| e.to_string()),
+    )]
+    max_filesize: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Drop the first <N> lines of text response body from output"
+    )]
+    skip_lines: u64,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Timeout for the whole request (e.g. 30, 1500ms), overriding config",
+        value_parser = |s: &str| data::parse_duration(s).map_err(|e| e.to_string()),
+    )]
+    timeout: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Retry up to <N> times on transient failures, overriding config"
+    )]
+    retry: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "Randomize backoff of retries: none, full or equal, overriding config"
+    )]
+    retry_jitter: Option<ReqRetryJitter>,
+
+    #[arg(
+        long,
+        value_name = "next=PATH",
+        help = "Follow pages by taking next cursor from JSON response at PATH (e.g. next=.meta.next_cursor)",
+        value_parser = parse_paginate,
+    )]
+    paginate: Option<String>,
+
+    #[arg(
+        long,
+        default_value = "cursor",
+        help = "Query parameter to send the next cursor with"
+    )]
+    page_param: String,
+
+    #[arg(
+        long,
+        default_value_t = 100,
+        help = "Maximum number of pages to follow"
+    )]
+    max_pages: usize,
+
+    #[arg(long, help = "Print paginated results as NDJSON instead of JSON array")]
+    ndjson: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the request to <FILE> as JSON before sending"
+    )]
+    dump_request: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Send the request written by --dump-request in <FILE> instead of a task"
+    )]
+    replay: Option<String>,
+
+    #[arg(
+        long = "exec",
+        value_name = "CMD",
+        conflicts_with = "OUTPUT",
+        help = "Pipe response body to stdin of shell command <CMD> instead of writing it"
+    )]
+    exec_command: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["OUTPUT", "exec_command", "fan_out"],
+        help = "Show response body with $PAGER (default: less) when stdout is a terminal"
+    )]
+    pager: bool,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["OUTPUT", "paginate"],
+        help = "Send the task to <URL> instead of its own, can be specified multiple times"
+    )]
+    fan_out: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Number of --fan-out requests sent at once"
+    )]
+    concurrency: u64,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Number of tasks run at once when multiple tasks are given"
+    )]
+    parallel: u64,
+
+    #[arg(long, help = "Print durations of the request to stderr")]
+    timing: bool,
+
+    #[arg(
+        long,
+        help = "Indent JSON response body, colored when stdout is a terminal and NO_COLOR is not set"
     )]
-    dryrun: bool,
+    pretty: bool,
+
+    #[arg(long, hide = true)]
+    complete_tasks: bool,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    #[command(about = "Warn about likely mistakes in task definitions without sending requests")]
+    Lint,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum DryrunFormat {
+    #[value(help = "Internal structure of the task")]
+    Debug,
+    #[value(help = "Request as a HTTP/1.1 message")]
+    Http,
+}
+
+fn parse_paginate(s: &str) -> Result<String, String> {
+    s.strip_prefix("next=")
+        .map(|path| path.to_string())
+        .ok_or_else(|| format!("expected next=PATH: {}", s))
 }
 
 impl Opt {
-    pub(crate) fn exec<R, W>(&self, r: &mut R, w: &mut W) -> anyhow::Result<ExitCode>
-    where
-        R: Read,
-        W: Write,
-    {
-        let input = if self.input == "-" {
+    fn read_input<R: Read>(&self, r: &mut R) -> anyhow::Result<String> {
+        if self.input == "-" {
             let mut buf = String::new();
             r.read_to_string(&mut buf)?;
-            buf
+            Ok(buf)
         } else {
             fs::read_to_string(self.input.as_str())
-                .context(format!("fail to open file: {}", self.input))?
-        };
-        let definitions = toml::from_str::<Req>(input.as_str())
-            .context(format!("malformed file: {}", self.input))?;
-
-        if self.name.is_none() {
-            print!("{}", definitions.display_tasks());
-            return Ok(ExitCode::SUCCESS);
+                .context(format!("fail to open file: {}", self.input))
         }
+    }
 
-        let name = self.name.as_ref().unwrap();
-        let definitions = definitions.with_values(self.variables.clone());
-        let task = if let Some(task) = definitions
-            .get_task(name)
-            .context("fail to resolve context")?
-        {
-            Ok(task)
-        } else {
-            Err(anyhow!("task `{}` is not defined", name))
-        }?;
+    fn input_path(&self) -> Option<&Path> {
+        Some(Path::new(&self.input)).filter(|_| self.input != "-")
+    }
 
-        if self.dryrun {
-            println!("{:#?}", task);
-            return Ok(ExitCode::SUCCESS);
-        }
+    fn format(&self) -> Option<ReqFormat> {
+        self.format
+            .or_else(|| self.input_path().and_then(ReqFormat::from_path))
+    }
 
-        if self.curl {
-            println!("{}", task.to_curl()?);
+    pub(crate) fn exec<R, W>(&self, r: &mut R, w: &mut W) -> anyhow::Result<ExitCode>
+    where
+        R: Read,
+        W: Write,
+    {
+        if self.complete_tasks {
+            // completion must not fail even if definitions are broken
+            let definitions = self
+                .read_input(r)
+                .and_then(|input| Req::parse(&input, self.format()))
+                .and_then(|definitions| definitions.resolve_includes(self.input_path()));
+            if let Ok(definitions) = definitions {
+                for name in definitions.task_names() {
+                    writeln!(w, "{}", name)?;
+                }
+            }
             return Ok(ExitCode::SUCCESS);
         }
 
-        let mut res = task.send().context("fail to send request")?;
-        if let Some(ref path) = self.output {
-            let f = std::fs::File::create(path)?;
-            let mut w = BufWriter::new(f);
-            download(&mut res, &mut w)?;
-            if self.include_header {
-                print_header(&res)?;
-            }
-        } else {
+        if let Some(ref path) = self.replay {
+            let dump = fs::read_to_string(path).context(format!("fail to open file: {}", path))?;
+            let dump = serde_json::from_str::<ReqDump>(&dump)
+                .context(format!("malformed request dump: {}", path))?;
+            let mut res = dump.send().context("fail to send request")?;
             let mut buf = vec![];
-            download(&mut res, &mut buf)?;
+            download(
+                &mut res,
+                &mut buf,
+                self.max_filesize,
+                None,
+                self.shows_progress(),
+            )?;
             if self.include_header {
                 print_header(&res)?;
             }
-            let mut out = BufWriter::new(w);
-            out.write(&buf)?;
+            w.write_all(&buf)?;
+            return Ok(if res.status().is_success() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
         }
 
-        let s = res.status();
-        if s.is_success() {
-            Ok(ExitCode::SUCCESS)
-        } else {
-            Ok(ExitCode::FAILURE)
+        let input = self.read_input(r)?;
+        let definitions = Req::parse(&input, self.format())
+            .context(format!("malformed file: {}", self.input))?
+            .resolve_includes(self.input_path())?;
+
+        if let Some(Command::Lint) = self.command {
+            let warnings = definitions.lint();
+            for warning in warnings.iter() {
+                writeln!(w, "warning: {}", warning)?;
+            }
+            return Ok(if warnings.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
         }
-    }
-}
 
-fn main() -> anyhow::Result<ExitCode> {
-    Opt::parse().exec(&mut stdin(), &mut stdout())
-}
+        if self.list_json {
+            writeln!(w, "{}", serde_json::to_string(&definitions.tasks_json())?)?;
+            return Ok(ExitCode::SUCCESS);
+        }
 
-fn download<W: Write>(res: &mut reqwest::blocking::Response, w: &mut W) -> anyhow::Result<()> {
-    let mut buf = [0; 64];
+        if self.name.is_empty() {
+            print!("{}", definitions.display_tasks());
+            return Ok(ExitCode::SUCCESS);
+        }
 
-    let pb = if let Some(len) = res.content_length() {
-        let style = ProgressStyle::default_bar()
-            .template(
-                "{spinner:.green} [{elapsed_precise}] [{bar:.green}] {bytes}/{total_bytes} ({bytes_per_sec})",
-            )?
-            .progress_chars("||.");
-        ProgressBar::new(len).with_style(style)
-    } else {
-        let style = ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] {bytes} ({bytes_per_sec})")?
-            .progress_chars("||.");
-        ProgressBar::new(0).with_style(style)
-    };
-    let mut progress: usize = 0;
+        let env_files = if self.no_env_file {
+            vec![]
+        } else if !self.env_file.is_empty() {
+            self.env_file.iter().map(PathBuf::from).collect()
+        } else {
+            definitions
+                .env_files()
+                .into_iter()
+                .map(Path::to_path_buf)
+                .collect()
+        };
+        let mut definitions = definitions;
+        for path in env_files {
+            let values = data::load_env_file(&path)?;
+            definitions = definitions.with_values(values, &format!("env file {}", path.display()));
+        }
+        let definitions = definitions
+            .with_values(self.variables.clone(), "-v")
+            .with_values(self.env_override.clone(), "--env-override");
+        let mut cookies = None;
+        let definitions = self.run_setup(definitions, &mut cookies)?;
+        if let [name] = self.name.as_slice() {
+            return self.exec_task(definitions, name, cookies, r, w);
+        }
+        if self.interactive {
+            return Err(anyhow!("--interactive cannot be used with multiple tasks"));
+        }
+        self.exec_tasks(&definitions, cookies, w)
+    }
 
-    loop {
-        let n = res.read(&mut buf[..])?;
-        if n == 0 {
-            pb.abandon();
-            break;
+    fn run_setup(
+        &self,
+        mut definitions: Req,
+        cookies: &mut Option<Arc<CookieJar>>,
+    ) -> anyhow::Result<Req> {
+        for i in 0.. {
+            let Some((task, _)) = definitions
+                .clone()
+                .get_setup(i)
+                .context("fail to resolve context")?
+            else {
+                break;
+            };
+            let captured = self
+                .send_capturing(task, cookies)
+                .context(format!("setup step {} failed", i + 1))?;
+            definitions = definitions.with_values(captured, &format!("setup step {}", i + 1));
+        }
+        Ok(definitions)
+    }
+
+    fn exec_tasks<W: Write>(
+        &self,
+        definitions: &Req,
+        cookies: Option<Arc<CookieJar>>,
+        w: &mut W,
+    ) -> anyhow::Result<ExitCode> {
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<_>>> = self.name.iter().map(|_| Mutex::new(None)).collect();
+        std::thread::scope(|scope| {
+            for _ in 0..self.parallel.min(self.name.len() as u64) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(name) = self.name.get(i) else {
+                        break;
+                    };
+                    let mut buf = vec![];
+                    let res = self
+                        .exec_task(
+                            definitions.clone(),
+                            name,
+                            cookies.clone(),
+                            &mut std::io::empty(),
+                            &mut buf,
+                        )
+                        .map(|code| (code, buf));
+                    *results[i].lock().unwrap() = Some(res);
+                });
+            }
+        });
+
+        let mut code = ExitCode::SUCCESS;
+        for (i, (name, result)) in self.name.iter().zip(results).enumerate() {
+            if i > 0 {
+                writeln!(w)?;
+            }
+            writeln!(w, "==> {} <==", name)?;
+            match result.into_inner().unwrap().expect("all tasks are run") {
+                Ok((task_code, body)) => {
+                    w.write_all(&body)?;
+                    if task_code != ExitCode::SUCCESS {
+                        code = ExitCode::FAILURE;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}: {:#}", name, e);
+                    code = ExitCode::FAILURE;
+                }
+            }
+        }
+        w.flush()?;
+        Ok(code)
+    }
+
+    fn exec_task<R, W>(
+        &self,
+        mut definitions: Req,
+        name: &str,
+        mut cookies: Option<Arc<CookieJar>>,
+        r: &mut R,
+        w: &mut W,
+    ) -> anyhow::Result<ExitCode>
+    where
+        R: Read,
+        W: Write,
+    {
+        let name = &definitions
+            .interpolate(name)
+            .context("fail to resolve task name")?;
+
+        if self.list_vars_used {
+            let vars = definitions
+                .variables_used(name)
+                .ok_or_else(|| anyhow!("task `{}` is not defined", name))?;
+            for (k, resolvable) in vars {
+                let status = if resolvable { "resolved" } else { "unresolved" };
+                writeln!(w, "{k}\t{status}")?;
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        for dep in definitions.dependencies(name)? {
+            let captured = self
+                .run_dependency(&definitions, &dep, &mut cookies)
+                .context(format!("dependency `{}` failed", dep))?;
+            definitions = definitions.with_values(captured, &format!("dependency `{}`", dep));
+        }
+
+        let resolved = loop {
+            match definitions.clone().get_task(name) {
+                Err(e) if self.interactive => {
+                    let key = match e.root() {
+                        InterpError::ValueNotFound(key) => key.clone(),
+                        _ => return Err(e).context("fail to resolve context"),
+                    };
+                    let value = prompt_variable(&key, r)?;
+                    definitions = definitions.with_values([(key, value)], "--interactive");
+                }
+                res => break res.context("fail to resolve context")?,
+            }
+        };
+        let (mut task, warnings) =
+            resolved.ok_or_else(|| anyhow!("task `{}` is not defined", name))?;
+
+        if self.strict_vars && !warnings.is_empty() {
+            let messages: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+            return Err(anyhow!("{}", messages.join("\n")).context("fail to resolve context"));
+        }
+        if self.warn_vars {
+            for warning in warnings.iter() {
+                eprintln!("warning: {}", warning);
+            }
+        }
+
+        if self.request_id {
+            task.config_mut().request_id = true;
+        }
+        if self.no_default_headers {
+            task.config_mut().default_headers = Some(false);
+        }
+        if self.trace_redirects {
+            task.config_mut().trace_redirects = true;
+        }
+        if !self.shows_progress() {
+            task.config_mut().no_progress = true;
+        }
+        if self.verbose {
+            task.config_mut().verbose = true;
+        }
+        if self.redact {
+            task.config_mut().redact = true;
+        }
+        if let Some(timeout) = self.timeout {
+            task.config_mut().set_timeout(timeout);
+        }
+        if let Some(retry) = self.retry {
+            task.config_mut().set_retry(retry);
+        }
+        if let Some(jitter) = self.retry_jitter {
+            task.config_mut().set_retry_jitter(jitter);
+        }
+        task.share_cookies(&mut cookies)?;
+        if let Some((header, id)) = task.ensure_request_id() {
+            eprintln!("{}: {}", header, id);
+        }
+        if !self.form_fields.is_empty() {
+            task.append_form(self.form_fields.clone())
+                .context("fail to apply --data-urlencode")?;
+        }
+
+        if self.explain {
+            writeln!(w, "read definitions from {}", self.input)?;
+            for line in definitions.explain(name).unwrap_or_default() {
+                writeln!(w, "{}", line)?;
+            }
+            for warning in warnings.iter() {
+                writeln!(w, "{}", warning)?;
+            }
+            writeln!(w, "request:")?;
+            write!(w, "{}", task.to_http()?)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        match self.dryrun {
+            Some(DryrunFormat::Debug) => {
+                println!("{:#?}", task);
+                return Ok(ExitCode::SUCCESS);
+            }
+            Some(DryrunFormat::Http) => {
+                write!(w, "{}", task.to_http()?)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            None => {}
+        }
+
+        if self.curl {
+            writeln!(w, "{}", task.to_curl()?)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        if self.print_url {
+            writeln!(w, "{}", task.url()?)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        let output = self
+            .output
+            .clone()
+            .or_else(|| task.output().map(String::from));
+
+        if let Some(ref path) = self.dump_request {
+            let dump = serde_json::to_string_pretty(&task.dump()?)?;
+            fs::write(path, dump + "\n").context(format!("fail to write file: {}", path))?;
+        }
+
+        if !self.fan_out.is_empty() {
+            if output.is_some() {
+                return Err(anyhow!("--fan-out cannot be used with output file"));
+            }
+            return self.fan_out(&task, w);
+        }
+
+        if let Some(ref path) = self.paginate {
+            return self.paginate(&mut task, path, output.as_deref(), w);
+        }
+
+        if !self.captures.is_empty() {
+            task.set_capture(self.captures.clone());
+            let captured = self
+                .send_capturing(task.clone(), &mut cookies)
+                .context("fail to apply --capture")?;
+            for (name, value) in captured {
+                writeln!(w, "{}={}", name, shell_quote(&value))?;
+            }
+            task.save_cookies()?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        if let Some(ref template) = self.template {
+            return self.print_template(&task, template, w);
+        }
+
+        let mut summary = Summary::default();
+        let mut code = ExitCode::SUCCESS;
+        let mut sink = std::io::sink();
+        for i in 0..self.repeat {
+            // bodies are dropped unless written to <OUTPUT>, to keep one JSON object per line
+            let mut body: &mut dyn Write = if self.json_lines { &mut sink } else { &mut *w };
+            let attempt = self.run(&task, output.as_deref(), &mut body).map_err(|e| {
+                if is_timeout(&e) {
+                    e.context(format!("task `{}` timed out", name))
+                } else {
+                    e
+                }
+            })?;
+            if !attempt.success {
+                code = match task.expect().and_then(|e| e.on_fail_exit) {
+                    Some(exit) if attempt.expect_failed => ExitCode::from(exit.get()),
+                    _ => ExitCode::FAILURE,
+                };
+            }
+            if self.timing {
+                eprint!("{}", attempt.timing);
+            }
+            if self.json_lines {
+                let line = serde_json::json!({
+                    "iter": i + 1,
+                    "status": attempt.status,
+                    "ms": attempt.timing.total.as_millis() as u64,
+                });
+                writeln!(w, "{}", line)?;
+                w.flush()?;
+            }
+            summary.record(&attempt);
+        }
+        if self.summary {
+            eprint!("{}", summary);
+        }
+        task.save_cookies()?;
+        Ok(code)
+    }
+
+    fn print_template<W: Write>(
+        &self,
+        task: &ReqTask,
+        template: &str,
+        w: &mut W,
+    ) -> anyhow::Result<ExitCode> {
+        std::thread::sleep(task.pre_request_delay()?);
+        let mut res = task.send().context("fail to send request")?;
+        let mut body = vec![];
+        download(
+            &mut res,
+            &mut body,
+            self.max_size(task)?,
+            task.read_timeout()?,
+            self.shows_progress(),
+        )?;
+        let line = template::render(
+            template,
+            res.status(),
+            res.headers(),
+            &body,
+            self.strict_template,
+        )
+        .context("fail to render --template")?;
+        writeln!(w, "{}", line)?;
+        task.save_cookies()?;
+        if res.status().is_success() {
+            Ok(ExitCode::SUCCESS)
+        } else {
+            Ok(ExitCode::FAILURE)
+        }
+    }
+
+    fn run_dependency(
+        &self,
+        definitions: &Req,
+        name: &str,
+        cookies: &mut Option<Arc<CookieJar>>,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        let (task, _) = definitions
+            .clone()
+            .get_task(name)
+            .context("fail to resolve context")?
+            .ok_or_else(|| anyhow!("task `{}` is not defined", name))?;
+        self.send_capturing(task, cookies)
+    }
+
+    fn send_capturing(
+        &self,
+        mut task: ReqTask,
+        cookies: &mut Option<Arc<CookieJar>>,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        task.share_cookies(cookies)?;
+        std::thread::sleep(task.pre_request_delay()?);
+        let mut res = task.send().context("fail to send request")?;
+        let mut body = vec![];
+        download(
+            &mut res,
+            &mut body,
+            self.max_size(&task)?,
+            task.read_timeout()?,
+            self.shows_progress(),
+        )?;
+        let checks_status = match task.expect() {
+            Some(expect) => {
+                let failures = expect.check(res.status(), res.headers(), &body)?;
+                if !failures.is_empty() {
+                    return Err(anyhow!("expectation failed: {}", failures.join(", ")));
+                }
+                expect.checks_status()
+            }
+            None => false,
+        };
+        if !checks_status && !res.status().is_success() {
+            return Err(anyhow!("responded {}", res.status()));
+        }
+        task.capture(res.headers(), &body)
+            .context("fail to capture response")
+    }
+
+    fn create_output(&self, path: &str) -> anyhow::Result<fs::File> {
+        if !self.no_create_dirs {
+            if let Some(dir) = Path::new(path)
+                .parent()
+                .filter(|d| !d.as_os_str().is_empty())
+            {
+                fs::create_dir_all(dir)
+                    .context(format!("fail to create directory: {}", dir.display()))?;
+            }
+        }
+        Ok(fs::File::create(path)?)
+    }
+
+    fn max_size(&self, task: &ReqTask) -> anyhow::Result<Option<u64>> {
+        match self.max_filesize {
+            Some(size) => Ok(Some(size)),
+            None => task.max_size(),
+        }
+    }
+
+    fn shows_progress(&self) -> bool {
+        !self.quiet && stderr().is_terminal()
+    }
+
+    fn pager_command(&self, is_terminal: bool) -> Option<String> {
+        if !self.pager
+            || self.json_lines
+            || self.snapshot.is_some()
+            || !is_terminal
+            || std::env::var_os("NO_PAGER").is_some()
+        {
+            return None;
+        }
+        Some(std::env::var("PAGER").unwrap_or_else(|_| "less".to_string()))
+    }
+
+    fn run<W: Write>(
+        &self,
+        task: &ReqTask,
+        output: Option<&str>,
+        w: &mut W,
+    ) -> anyhow::Result<Attempt> {
+        std::thread::sleep(task.pre_request_delay()?);
+        let start = Instant::now();
+        let mut res = task.send().context("fail to send request")?;
+        let headers_received = start.elapsed();
+        if self.fail && !res.status().is_success() {
+            if self.include_header {
+                print_header(&res)?;
+            }
+            eprintln!("responded {}", res.status());
+            if let Some(ref path) = self.meta_out {
+                write_meta(&res, start.elapsed(), self.create_output(path)?)?;
+            }
+            return Ok(Attempt {
+                success: false,
+                expect_failed: false,
+                status: res.status().as_u16(),
+                bytes: 0,
+                timing: Timing {
+                    headers: headers_received,
+                    first_byte: None,
+                    total: start.elapsed(),
+                },
+            });
+        }
+        let read_timeout = task.read_timeout()?;
+        let max_size = self.max_size(task)?;
+        let progress = self.shows_progress();
+        let skip_lines = if is_text(&res) { self.skip_lines } else { 0 };
+        let mut command_ok = true;
+        let mut headers_ok = true;
+        for name in self.require_header.iter() {
+            if !res.headers().contains_key(name.as_str()) {
+                eprintln!("missing response header: {}", name);
+                headers_ok = false;
+            }
+        }
+        let (body, bytes, first_byte) = if let Some(path) = output {
+            let f = self.create_output(path)?;
+            let mut w = FirstByte::new(SkipLines::new(BufWriter::new(f), skip_lines));
+            let bytes = match download(&mut res, &mut w, max_size, read_timeout, progress) {
+                Ok(n) => n,
+                Err(e) => {
+                    drop(w);
+                    fs::remove_file(path)?;
+                    return Err(e);
+                }
+            };
+            if self.include_header {
+                print_header(&res)?;
+            }
+            (None, bytes, w.at)
+        } else if let Some(ref cmd) = self
+            .exec_command
+            .clone()
+            .or_else(|| self.pager_command(stdout().is_terminal()))
+        {
+            let mut child = shell_command(cmd)
+                .stdin(Stdio::piped())
+                .spawn()
+                .context(format!("fail to run command: {}", cmd))?;
+            let stdin = child.stdin.take().expect("stdin of child is piped");
+            let mut stdin = FirstByte::new(SkipLines::new(stdin, skip_lines));
+            let downloaded = download(&mut res, &mut stdin, max_size, read_timeout, progress);
+            let first_byte = stdin.at;
+            drop(stdin);
+            let status = child.wait()?;
+            let bytes = downloaded?;
+            if self.include_header {
+                print_header(&res)?;
+            }
+            if !status.success() {
+                eprintln!("command `{}` failed: {}", cmd, status);
+                command_ok = false;
+            }
+            (None, bytes, first_byte)
+        } else {
+            let mut buf = FirstByte::new(vec![]);
+            let bytes = download(&mut res, &mut buf, max_size, read_timeout, progress)?;
+            let FirstByte { inner: buf, at } = buf;
+            if self.include_header {
+                print_header(&res)?;
+            }
+            let mut shown = vec![];
+            SkipLines::new(&mut shown, skip_lines).write_all(&buf)?;
+            let json = if self.pretty && is_json(&res) {
+                serde_json::from_slice::<serde_json::Value>(&shown).ok()
+            } else {
+                None
+            };
+            let mut out = BufWriter::new(&mut *w);
+            match json {
+                Some(json) => {
+                    let color = stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+                    let mut pretty = String::new();
+                    write_pretty_json(&json, color, 0, &mut pretty);
+                    writeln!(out, "{}", pretty)?;
+                }
+                None => out.write_all(&shown)?,
+            }
+            (Some(buf), bytes, at)
+        };
+
+        let timing = Timing {
+            headers: headers_received,
+            first_byte: first_byte.map(|at| at - start),
+            total: start.elapsed(),
+        };
+        if let Some(ref path) = self.meta_out {
+            write_meta(&res, timing.total, self.create_output(path)?)?;
+        }
+
+        let body = match (body, output) {
+            (Some(body), _) => body,
+            (None, Some(path)) if task.expect().is_some() || self.snapshot.is_some() => {
+                fs::read(path)?
+            }
+            _ => vec![],
+        };
+        let snapshot_ok = match self.snapshot {
+            Some(ref path) => self.check_snapshot(path, &body)?,
+            None => true,
+        };
+
+        if let Some(expect) = task.expect() {
+            let failures = expect.check(res.status(), res.headers(), &body)?;
+            for failure in failures.iter() {
+                eprintln!("expectation failed: {}", failure);
+            }
+            if !failures.is_empty() || expect.checks_status() {
+                return Ok(Attempt {
+                    success: failures.is_empty() && command_ok && headers_ok && snapshot_ok,
+                    expect_failed: !failures.is_empty(),
+                    status: res.status().as_u16(),
+                    bytes,
+                    timing,
+                });
+            }
+        }
+
+        Ok(Attempt {
+            success: res.status().is_success() && command_ok && headers_ok && snapshot_ok,
+            expect_failed: false,
+            status: res.status().as_u16(),
+            bytes,
+            timing,
+        })
+    }
+
+    fn check_snapshot(&self, path: &str, body: &[u8]) -> anyhow::Result<bool> {
+        if !Path::new(path).exists() {
+            fs::write(path, body).context(format!("fail to save snapshot: {}", path))?;
+            eprintln!("snapshot saved: {}", path);
+            return Ok(true);
+        }
+        let baseline = fs::read(path).context(format!("fail to open snapshot: {}", path))?;
+        let json = |bytes: &[u8]| serde_json::from_slice::<serde_json::Value>(bytes).ok();
+        let diffs = match (json(&baseline), json(body)) {
+            (Some(mut expected), Some(mut actual)) => {
+                for p in self.diff_ignore.iter() {
+                    data::remove_json_path(&mut expected, p);
+                    data::remove_json_path(&mut actual, p);
+                }
+                data::json_differences(&expected, &actual)
+            }
+            _ if baseline == body => vec![],
+            _ if self.ignore_whitespace
+                && normalize_whitespace(&baseline) == normalize_whitespace(body) =>
+            {
+                vec![]
+            }
+            _ => vec!["body differs".to_string()],
+        };
+        for diff in diffs.iter() {
+            eprintln!("snapshot {}: {}", path, diff);
+        }
+        Ok(diffs.is_empty())
+    }
+
+    fn paginate<W: Write>(
+        &self,
+        task: &mut ReqTask,
+        path: &str,
+        output: Option<&str>,
+        w: &mut W,
+    ) -> anyhow::Result<ExitCode> {
+        let mut pages = vec![];
+        let mut code = ExitCode::SUCCESS;
+        let mut cursor = None;
+        for _ in 0..self.max_pages {
+            std::thread::sleep(task.pre_request_delay()?);
+            let res = task.send().context("fail to send request")?;
+            if !res.status().is_success() {
+                eprintln!("page {} responded {}", pages.len() + 1, res.status());
+                code = ExitCode::FAILURE;
+                cursor = None;
+                break;
+            }
+            let page: serde_json::Value = res
+                .json()
+                .context(format!("page {} is not valid JSON", pages.len() + 1))?;
+            cursor = match data::lookup_json(&page, path) {
+                Some(serde_json::Value::String(s)) if !s.is_empty() => Some(s.clone()),
+                Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+                _ => None,
+            };
+            pages.push(page);
+            match cursor {
+                Some(ref c) => task.set_query(&self.page_param, c.clone()),
+                None => break,
+            }
+        }
+        if cursor.is_some() {
+            eprintln!("stopped after {} pages (--max-pages)", self.max_pages);
+        }
+
+        let mut out: BufWriter<Box<dyn Write + '_>> = match output {
+            Some(path) => BufWriter::new(Box::new(self.create_output(path)?)),
+            None => BufWriter::new(Box::new(w)),
+        };
+        if self.ndjson {
+            for page in pages.iter() {
+                writeln!(out, "{}", serde_json::to_string(page)?)?;
+            }
+        } else {
+            writeln!(out, "{}", serde_json::to_string(&pages)?)?;
+        }
+        out.flush()?;
+        Ok(code)
+    }
+
+    fn fan_out<W: Write>(&self, task: &ReqTask, w: &mut W) -> anyhow::Result<ExitCode> {
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<_>>> =
+            self.fan_out.iter().map(|_| Mutex::new(None)).collect();
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(self.fan_out.len() as u64) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(url) = self.fan_out.get(i) else {
+                        break;
+                    };
+                    let mut task = task.clone();
+                    task.set_url(url.clone());
+                    let mut buf = vec![];
+                    let res = self.run(&task, None, &mut buf).map(|a| (a, buf));
+                    *results[i].lock().unwrap() = Some(res);
+                });
+            }
+        });
+
+        let mut code = ExitCode::SUCCESS;
+        for (url, result) in self.fan_out.iter().zip(results) {
+            match result.into_inner().unwrap().expect("all urls are sent") {
+                Ok((attempt, body)) => {
+                    w.write_all(&body)?;
+                    if self.timing {
+                        eprint!("{}", attempt.timing);
+                    }
+                    if !attempt.success {
+                        code = ExitCode::FAILURE;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}: {:#}", url, e);
+                    code = ExitCode::FAILURE;
+                }
+            }
+        }
+        w.flush()?;
+        Ok(code)
+    }
+}
+
+struct Attempt {
+    success: bool,
+    expect_failed: bool,
+    status: u16,
+    bytes: u64,
+    timing: Timing,
+}
+
+struct Timing {
+    headers: Duration,
+    first_byte: Option<Duration>,
+    total: Duration,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "headers     {:.2?}", self.headers)?;
+        match self.first_byte {
+            Some(first_byte) => writeln!(f, "first-byte  {:.2?}", first_byte)?,
+            None => writeln!(f, "first-byte  -")?,
+        }
+        writeln!(f, "total       {:.2?}", self.total)
+    }
+}
+
+struct FirstByte<W> {
+    inner: W,
+    at: Option<Instant>,
+}
+
+impl<W> FirstByte<W> {
+    fn new(inner: W) -> Self {
+        FirstByte { inner, at: None }
+    }
+}
+
+impl<W: Write> Write for FirstByte<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.at.is_none() && !buf.is_empty() {
+            self.at = Some(Instant::now());
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct SkipLines<W> {
+    inner: W,
+    remaining: u64,
+}
+
+impl<W> SkipLines<W> {
+    fn new(inner: W, lines: u64) -> Self {
+        SkipLines {
+            inner,
+            remaining: lines,
+        }
+    }
+}
+
+impl<W: Write> Write for SkipLines<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut rest = buf;
+        while self.remaining > 0 {
+            match rest.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    rest = &rest[i + 1..];
+                    self.remaining -= 1;
+                }
+                None => return Ok(buf.len()),
+            }
+        }
+        self.inner.write_all(rest)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn is_text(res: &reqwest::blocking::Response) -> bool {
+    match res.headers().get(reqwest::header::CONTENT_TYPE) {
+        Some(v) => {
+            let v = v.to_str().unwrap_or_default().to_ascii_lowercase();
+            v.starts_with("text/") || v.contains("json") || v.contains("xml")
+        }
+        None => true,
+    }
+}
+
+fn is_json(res: &reqwest::blocking::Response) -> bool {
+    res.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().contains("json"))
+}
+
+fn write_pretty_json(val: &serde_json::Value, color: bool, depth: usize, out: &mut String) {
+    use serde_json::Value;
+    let paint = |code: &str, s: &str, out: &mut String| {
+        if color {
+            out.push_str(&format!("\x1b[{}m{}\x1b[0m", code, s));
+        } else {
+            out.push_str(s);
+        }
+    };
+    let indent = |depth: usize, out: &mut String| out.push_str(&"  ".repeat(depth));
+    match val {
+        Value::Null | Value::Bool(_) => paint("33", &val.to_string(), out),
+        Value::Number(_) => paint("36", &val.to_string(), out),
+        Value::String(_) => paint("32", &val.to_string(), out),
+        Value::Array(a) if a.is_empty() => out.push_str("[]"),
+        Value::Object(m) if m.is_empty() => out.push_str("{}"),
+        Value::Array(a) => {
+            out.push_str("[\n");
+            for (i, v) in a.iter().enumerate() {
+                indent(depth + 1, out);
+                write_pretty_json(v, color, depth + 1, out);
+                out.push_str(if i + 1 < a.len() { ",\n" } else { "\n" });
+            }
+            indent(depth, out);
+            out.push(']');
+        }
+        Value::Object(m) => {
+            out.push_str("{\n");
+            for (i, (k, v)) in m.iter().enumerate() {
+                indent(depth + 1, out);
+                paint("34;1", &Value::String(k.clone()).to_string(), out);
+                out.push_str(": ");
+                write_pretty_json(v, color, depth + 1, out);
+                out.push_str(if i + 1 < m.len() { ",\n" } else { "\n" });
+            }
+            indent(depth, out);
+            out.push('}');
+        }
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    success: usize,
+    failure: usize,
+    latencies: Vec<Duration>,
+    bytes: u64,
+}
+
+impl Summary {
+    fn record(&mut self, attempt: &Attempt) {
+        if attempt.success {
+            self.success += 1;
+        } else {
+            self.failure += 1;
+        }
+        self.latencies.push(attempt.timing.total);
+        self.bytes += attempt.bytes;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "requests  {}", self.success + self.failure)?;
+        writeln!(f, "success   {}", self.success)?;
+        writeln!(f, "failure   {}", self.failure)?;
+        if let (Some(min), Some(max)) = (self.latencies.iter().min(), self.latencies.iter().max()) {
+            let avg = self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32;
+            writeln!(
+                f,
+                "latency   min {:.2?} / max {:.2?} / avg {:.2?}",
+                min, max, avg
+            )?;
+        }
+        writeln!(f, "bytes     {}", self.bytes)
+    }
+}
+
+fn is_timeout(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            e.is_timeout()
+        } else if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            e.kind() == std::io::ErrorKind::TimedOut
+        } else {
+            false
+        }
+    })
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["secret", "token", "password", "passwd", "key", "auth"]
+        .iter()
+        .any(|s| name.contains(s))
+}
+
+fn prompt_variable<R: Read>(name: &str, r: &mut R) -> anyhow::Result<String> {
+    eprint!("{}: ", name);
+    if is_secret(name) && stdin().is_terminal() {
+        return Ok(rpassword::read_password()?);
+    }
+    let mut line = vec![];
+    let mut byte = [0; 1];
+    loop {
+        if r.read(&mut byte)? == 0 {
+            if line.is_empty() {
+                eprintln!();
+                return Err(anyhow!("aborted while prompting value of \"{}\"", name));
+            }
+            break;
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    let line = String::from_utf8(line).context(format!("invalid value of \"{}\"", name))?;
+    Ok(line.trim_end_matches('\r').to_string())
+}
+
+// tests touching process environment must hold this lock, shared by tests of all modules
+#[cfg(test)]
+pub(crate) static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+fn main() -> anyhow::Result<ExitCode> {
+    Opt::parse().exec(&mut stdin(), &mut stdout())
+}
+
+fn shell_quote(value: &str) -> Cow<'_, str> {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.,:/@%+=".contains(c));
+    if plain {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("'{}'", value.replace('\'', "'\\''")))
+    }
+}
+
+fn shell_command(cmd: &str) -> std::process::Command {
+    if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", cmd]);
+        command
+    } else {
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", cmd]);
+        command
+    }
+}
+
+struct StallReader {
+    rx: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    window: Duration,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl StallReader {
+    fn spawn<R: Read + Send + 'static>(mut inner: R, window: Duration) -> Self {
+        let (tx, rx) = mpsc::sync_channel(1);
+        std::thread::spawn(move || loop {
+            let mut buf = vec![0; 8192];
+            let res = inner.read(&mut buf).map(|n| {
+                buf.truncate(n);
+                buf
+            });
+            let done = !matches!(res, Ok(ref buf) if !buf.is_empty());
+            if tx.send(res).is_err() || done {
+                break;
+            }
+        });
+        StallReader {
+            rx,
+            window,
+            chunk: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl Read for StallReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            self.chunk = match self.rx.recv_timeout(self.window) {
+                Ok(chunk) => chunk?,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("no bytes of response body arrived for {:?}", self.window),
+                    ))
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => vec![],
+            };
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn take_body(res: &mut reqwest::blocking::Response) -> reqwest::blocking::Response {
+    let mut rest = http::Response::new(vec![]);
+    *rest.status_mut() = res.status();
+    *rest.version_mut() = res.version();
+    *rest.headers_mut() = res.headers().clone();
+    std::mem::replace(res, rest.into())
+}
+
+fn download<W: Write>(
+    res: &mut reqwest::blocking::Response,
+    w: &mut W,
+    limit: Option<u64>,
+    read_timeout: Option<Duration>,
+    show_progress: bool,
+) -> anyhow::Result<u64> {
+    let mut buf = [0; 64];
+
+    if let (Some(limit), Some(len)) = (limit, res.content_length()) {
+        if len > limit {
+            return Err(anyhow!(
+                "response body ({} bytes) exceeds maximum size ({} bytes)",
+                len,
+                limit
+            ));
+        }
+    }
+
+    let pb = if !show_progress {
+        ProgressBar::hidden()
+    } else if let Some(len) = res.content_length() {
+        let style = ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:.green}] {bytes}/{total_bytes} ({bytes_per_sec})",
+            )?
+            .progress_chars("||.");
+        ProgressBar::new(len).with_style(style)
+    } else {
+        let style = ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] {bytes} ({bytes_per_sec})")?
+            .progress_chars("||.");
+        ProgressBar::new(0).with_style(style)
+    };
+    let mut progress: usize = 0;
+
+    let mut body: Box<dyn Read> = match read_timeout {
+        Some(window) => Box::new(StallReader::spawn(take_body(res), window)),
+        None => Box::new(&mut *res),
+    };
+    loop {
+        let n = body.read(&mut buf[..])?;
+        if n == 0 {
+            pb.abandon();
+            break;
         }
         progress += n;
+        if let Some(limit) = limit {
+            if progress as u64 > limit {
+                pb.abandon();
+                return Err(anyhow!(
+                    "response body exceeds maximum size ({} bytes)",
+                    limit
+                ));
+            }
+        }
         pb.set_position(progress as u64);
-        w.write(&buf[..n])?;
+        w.write_all(&buf[..n])?;
+    }
+
+    w.flush()?;
+    Ok(progress as u64)
+}
+
+fn print_header(res: &reqwest::blocking::Response) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(stdout());
+    data::write_response_head(&mut out, res, "")?;
+    Ok(())
+}
+
+fn normalize_whitespace(text: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(text)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn write_meta<W: Write>(
+    res: &reqwest::blocking::Response,
+    elapsed: Duration,
+    w: W,
+) -> anyhow::Result<()> {
+    let mut headers = serde_json::Map::new();
+    for key in res.headers().keys() {
+        let mut values: Vec<_> = res
+            .headers()
+            .get_all(key)
+            .iter()
+            .map(|v| serde_json::Value::from(String::from_utf8_lossy(v.as_bytes())))
+            .collect();
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            serde_json::Value::from(values)
+        };
+        headers.insert(key.to_string(), value);
+    }
+    let meta = serde_json::json!({
+        "status": res.status().as_u16(),
+        "headers": headers,
+        "url": res.url().as_str(),
+        "elapsed_ms": elapsed.as_millis() as u64,
+    });
+    let mut w = BufWriter::new(w);
+    serde_json::to_writer_pretty(&mut w, &meta)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use httpmock::Method;
+    use rstest::{fixture, rstest};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[fixture]
+    fn server() -> MockServer {
+        MockServer::start()
+    }
+
+    #[rstest]
+    #[case("get", Method::GET)]
+    #[case("post", Method::POST)]
+    #[case("put", Method::PUT)]
+    #[case("delete", Method::DELETE)]
+    #[case("head", Method::HEAD)]
+    #[case("options", Method::OPTIONS)]
+    #[case("patch", Method::PATCH)]
+    #[case("trace", Method::TRACE)]
+    fn test_method(server: MockServer, #[case] task: &str, #[case] method: Method) {
+        let input = format!(
+            r#"
+                [tasks.{}]
+                {} = "http://{}/{}"
+            "#,
+            task,
+            method,
+            server.address(),
+            task,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", task]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(method).path(format!("/{}", task));
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_task_from_env(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.from_env]
+                GET = "http://{}/from_env"
+            "#,
+            server.address(),
+        );
+        let opt = {
+            let _lock = ENV_LOCK.lock().unwrap();
+            std::env::set_var("REQ_TASK", "from_env");
+            let opt = Opt::try_parse_from(vec!["req", "-f", "-"]);
+            let explicit = Opt::try_parse_from(vec!["req", "-f", "-", "explicit"]);
+            std::env::remove_var("REQ_TASK");
+            assert_eq!(explicit.unwrap().name, vec!["explicit"]);
+            opt.unwrap()
+        };
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/from_env");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_connect(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.connect]
+                CONNECT = "http://{}"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "connect"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::CONNECT).path("");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_get_with_queries(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get_with_queries]
+                GET = "http://{}/get_with_queries"
+
+                [tasks.get_with_queries.queries]
+                foo = "FOO"
+                bar = "BAR"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get_with_queries"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get_with_queries")
+                .query_param("foo", "FOO")
+                .query_param("bar", "BAR");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case("repeat", &[("a", "1"), ("a", "2"), ("b", "3")])]
+    #[case("comma", &[("a", "1,2"), ("b", "3")])]
+    #[case("brackets", &[("a[]", "1"), ("a[]", "2"), ("b[]", "3")])]
+    fn test_array_query_style(
+        server: MockServer,
+        #[case] style: &str,
+        #[case] expected: &[(&str, &str)],
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get_with_array]
+                GET = "http://{}/get_with_array"
+
+                [tasks.get_with_array.queries]
+                a = ["1", "2"]
+                b = ["3"]
+
+                [config]
+                array-query-style = "{}"
+            "#,
+            server.address(),
+            style,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get_with_array"]).unwrap();
+        let mock = server.mock(|when, then| {
+            expected.iter().fold(
+                when.method(Method::GET).path("/get_with_array"),
+                |when, (k, v)| when.query_param(*k, *v),
+            );
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_get_with_headers(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get_with_headers]
+                GET = "http://{}/get_with_headers"
+
+                [tasks.get_with_headers.headers]
+                "X-Authorization" = "Bearer HOGE"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get_with_headers"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get_with_headers")
+                .header("X-Authorization", "Bearer HOGE");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_body(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.post_with_body]
+                POST = "http://{}/post_with_body"
+
+                [tasks.post_with_body.body]
+                plain = "hello"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_body"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_body")
+                .body("hello");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_json(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.post_with_json]
+                POST = "http://{}/post_with_json"
+
+                [tasks.post_with_json.body.json]
+                str = "hello"
+                num = 42
+                bool = true
+                obj = {{ "foo"="bar" }}
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_json"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_json")
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "str": "hello",
+                    "num": 42,
+                    "bool": true,
+                    "obj": { "foo": "bar" },
+                }));
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_xml(server: MockServer) {
+        let input = format!(
+            r#"
+                [variables]
+                NAME = "req"
+
+                [tasks.post_with_xml]
+                POST = "http://{}/post_with_xml"
+                body.xml = "<user><name>${{NAME}}</name></user>"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_xml"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_xml")
+                .header("content-type", "application/xml")
+                .body("<user><name>req</name></user>");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_ndjson(server: MockServer) {
+        let input = format!(
+            r#"
+                [variables]
+                INDEX = "logs"
+
+                [tasks.bulk]
+                POST = "http://{}/_bulk"
+                body.ndjson = [
+                    {{ index = {{ _index = "${{INDEX}}" }} }},
+                    {{ message = "hello" }},
+                ]
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "bulk"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/_bulk")
+                .header("content-type", "application/x-ndjson")
+                .body("{\"index\":{\"_index\":\"logs\"}}\n{\"message\":\"hello\"}\n");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_patch_with_json_patch(server: MockServer) {
+        let input = format!(
+            r#"
+                [variables]
+                NAME = "req"
+
+                [tasks.patch_with_json_patch]
+                PATCH = "http://{}/patch_with_json_patch"
+
+                [[tasks.patch_with_json_patch.body.json-patch]]
+                op = "replace"
+                path = "/name"
+                value = "${{NAME}}"
+
+                [[tasks.patch_with_json_patch.body.json-patch]]
+                op = "remove"
+                path = "/obsolete"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "patch_with_json_patch"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::PATCH)
+                .path("/patch_with_json_patch")
+                .header("content-type", "application/json-patch+json")
+                .json_body(json!([
+                    { "op": "replace", "path": "/name", "value": "req" },
+                    { "op": "remove", "path": "/obsolete" },
+                ]));
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_form(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.post_with_form]
+                POST = "http://{}/post_with_form"
+
+                [tasks.post_with_form.body.form]
+                foo = "FOO"
+                bar = "BAR"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_form"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_form")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .x_www_form_urlencoded_tuple("foo", "FOO")
+                .x_www_form_urlencoded_tuple("bar", "BAR");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_form_file(server: MockServer) {
+        let path = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::write(&path, "hello & world").unwrap();
+        let input = format!(
+            r#"
+                [tasks.post_with_form]
+                POST = "http://{}/post_with_form"
+
+                [tasks.post_with_form.body.form]
+                foo = "FOO"
+                description = {{ file = "{}" }}
+            "#,
+            server.address(),
+            path.display(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_form"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_form")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .x_www_form_urlencoded_tuple("foo", "FOO")
+                .x_www_form_urlencoded_tuple("description", "hello & world");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_form_file_interpolated(server: MockServer) {
+        let dir = std::env::temp_dir();
+        let name = format!("req-test-{}", Uuid::new_v4());
+        fs::write(dir.join(&name), "from ${NAME}").unwrap();
+        let input = format!(
+            r#"
+                variables.DIR = "{}"
+                variables.NAME = "{}"
+
+                [tasks.post_with_form]
+                POST = "http://{}/post_with_form"
+                body.form.description.file = "${{DIR}}/${{NAME}}"
+            "#,
+            dir.display(),
+            name,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_form"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_form")
+                .x_www_form_urlencoded_tuple("description", "from ${NAME}");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+        fs::remove_file(dir.join(&name)).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_data_urlencode(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.post_with_form]
+                POST = "http://{}/post_with_form"
+
+                [tasks.post_with_form.body.form]
+                foo = "FOO"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "post_with_form",
+            "--data-urlencode",
+            "q=a b&c=d",
+        ])
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_form")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .body("foo=FOO&q=a+b%26c%3Dd");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_multipart(server: MockServer) {
+        let uuid = Uuid::new_v4();
+
+        let input = format!(
+            r#"
+                [tasks.post_with_multipart]
+                POST = "http://{}/post_with_multipart"
+
+                [tasks.post_with_multipart.body.multipart]
+                uuid = "{}"
+                foo = "FOO"
+            "#,
+            server.address(),
+            uuid,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_multipart"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_multipart")
+                .body_contains(uuid.to_string());
+            then.status(200).body("ok");
+        });
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_post_with_file(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.post_with_multipart]
+                POST = "http://{}/post_with_multipart"
+
+                [tasks.post_with_multipart.body.multipart]
+                "Cargo.toml".file = "Cargo.toml"
+            "#,
+            server.address(),
+        );
+        let content = fs::read("Cargo.toml").unwrap();
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_multipart"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post_with_multipart")
+                .body_contains(String::from_utf8(content).unwrap());
+            then.status(200).body("ok");
+        });
+        
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case("", vec!["--max-filesize", "1KB"])]
+    #[case("", vec!["--max-size", "1KB"])]
+    #[case("config.max-size = \"1KB\"", vec![])]
+    #[case("config.max-size = 1000", vec![])]
+    #[case("config.max-size = \"1MB\"", vec!["--max-filesize", "1KB"])]
+    fn test_max_filesize(server: MockServer, #[case] config: &str, #[case] args: Vec<&str>) {
+        let input = format!(
+            r#"
+                [tasks.download]
+                GET = "http://{}/download"
+                {}
+            "#,
+            server.address(),
+            config,
+        );
+        let path = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        let opt = Opt::try_parse_from(
+            [
+                vec![
+                    "req",
+                    "-f",
+                    "-",
+                    "download",
+                    "--out",
+                    path.to_str().unwrap(),
+                ],
+                args,
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/download");
+            then.status(200).body("x".repeat(2000));
+        });
+
+        let res = opt.exec(&mut input.as_bytes(), &mut std::io::empty());
+
+        mock.assert();
+        assert!(res.is_err(), "result: {:#?}", res);
+        assert!(!path.exists());
+    }
+
+    #[rstest]
+    fn test_max_size_within(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.download]
+                GET = "http://{}/download"
+                config.max-size = "1KB"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "download"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/download");
+            then.status(200).body("x".repeat(1000));
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out.len(), 1000);
+    }
+
+    #[rstest]
+    fn test_redirect(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.redirect]
+                GET = "http://{}/redirect/0"
+
+                [config]
+                redirect = 2
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "redirect"]).unwrap();
+        let mock_first = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/redirect/0");
+            then.status(302).header("Location", server.url("/redirect/1"));
+        });
+        let mock_second = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/redirect/1");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock_first.assert();
+        mock_second.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_too_many_redirect(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.redirect]
+                GET = "http://{}/redirect/0"
+
+                [config]
+                redirect = 2
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "redirect"]).unwrap();
+        let mock_first = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/redirect/0");
+            then.status(302).header("Location", server.url("/redirect/1"));
+        });
+        let mock_second = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/redirect/1");
+            then.status(302).header("Location", server.url("/redirect/2"));
+        });
+
+        let res = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty());
+
+        mock_first.assert();
+        mock_second.assert();
+        assert!(res.is_err(), "result: {:#?}", res);
+    }
+
+    #[rstest]
+    #[case(vec!["--request-id"], "", "x-request-id")]
+    #[case(vec![], "request-id = true\nrequest-id-header = \"X-Correlation-Id\"", "x-correlation-id")]
+    fn test_request_id(
+        server: MockServer,
+        #[case] args: Vec<&str>,
+        #[case] config: &str,
+        #[case] header: &str,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [config]
+                {}
+            "#,
+            server.address(),
+            config,
+        );
+        let opt = Opt::try_parse_from([vec!["req", "-f", "-", "get"], args].concat()).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header_exists(header)
+                .matches(|req| {
+                    req.headers
+                        .iter()
+                        .flatten()
+                        .any(|(k, v)| k.ends_with("-id") && Uuid::parse_str(v).is_ok())
+                });
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case(r#"{"status": "ok"}"#, ExitCode::SUCCESS)]
+    #[case(r#"{"status": "ng"}"#, ExitCode::FAILURE)]
+    fn test_expect_body_matches(
+        server: MockServer,
+        #[case] body: &str,
+        #[case] expected: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [tasks.get.expect]
+                body-matches = '"status"\s*:\s*"ok"'
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body(body);
+        });
+
+        let code = opt.exec(&mut input.as_bytes(), &mut vec![]).unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+    }
+
+    #[rstest]
+    #[case("application/json", r#"{"status": "ok"}"#, ExitCode::SUCCESS)]
+    #[case("text/plain", r#"{"status": "ok"}"#, ExitCode::FAILURE)]
+    #[case("application/json", r#"{"status": "ng"}"#, ExitCode::FAILURE)]
+    fn test_expect_body_contains_and_header(
+        server: MockServer,
+        #[case] content_type: &str,
+        #[case] body: &str,
+        #[case] expected: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [tasks.get.expect]
+                body-contains = '"ok"'
+                header = {{ Content-Type = "application/json" }}
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200)
+                .header("content-type", content_type)
+                .body(body);
+        });
+
+        let code = opt.exec(&mut input.as_bytes(), &mut vec![]).unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+    }
+
+    #[rstest]
+    #[case("status = \"2xx\"", 204, ExitCode::SUCCESS)]
+    #[case("status = \"2xx\"", 301, ExitCode::FAILURE)]
+    #[case("status = 404", 404, ExitCode::SUCCESS)]
+    #[case("status = [200, 201]", 201, ExitCode::SUCCESS)]
+    #[case("status = [200, 201]", 204, ExitCode::FAILURE)]
+    #[case("status-range = [200, 299]", 204, ExitCode::SUCCESS)]
+    #[case("status-range = [200, 299]", 301, ExitCode::FAILURE)]
+    #[case("status-range = [400, 499]", 404, ExitCode::SUCCESS)]
+    fn test_expect_status(
+        server: MockServer,
+        #[case] expect: &str,
+        #[case] status: u16,
+        #[case] expected: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [tasks.get.expect]
+                {}
+            "#,
+            server.address(),
+            expect,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(status);
+        });
+
+        let code = opt.exec(&mut input.as_bytes(), &mut vec![]).unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+    }
+
+    #[rstest]
+    fn test_expect_invalid_pattern(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [tasks.get.expect]
+                body-matches = "("
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("ok");
+        });
+
+        let res = opt.exec(&mut input.as_bytes(), &mut vec![]);
+
+        assert!(res.is_err(), "result: {:#?}", res);
+    }
+
+    #[rstest]
+    fn test_trace_redirects(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.redirect]
+                GET = "http://{}/first"
+                config.redirect = 2
+            "#,
+            server.address(),
+        );
+        let opt =
+            Opt::try_parse_from(vec!["req", "-f", "-", "--trace-redirects", "redirect"]).unwrap();
+        let first = server.mock(|when, then| {
+            when.method(Method::GET).path("/first");
+            then.status(302).header("location", server.url("/second"));
+        });
+        let second = server.mock(|when, then| {
+            when.method(Method::GET).path("/second");
+            then.status(301).header("location", server.url("/last"));
+        });
+        let last = server.mock(|when, then| {
+            when.method(Method::GET).path("/last");
+            then.status(200).body("done");
+        });
+
+        let mut buf = vec![];
+        let code = opt.exec(&mut input.as_bytes(), &mut buf).unwrap();
+
+        first.assert();
+        second.assert();
+        last.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(buf).unwrap(), "done");
+    }
+
+    #[rstest]
+    fn test_redirect_hop() {
+        let from = reqwest::Url::parse("http://localhost/first").unwrap();
+        let to = reqwest::Url::parse("http://localhost/second").unwrap();
+        assert_eq!(
+            data::redirect_hop(reqwest::StatusCode::FOUND, &from, &to),
+            "< 302 http://localhost/first -> http://localhost/second"
+        );
+    }
+
+    #[rstest]
+    #[case(vec!["--no-default-headers"], "")]
+    #[case(vec![], "default-headers = false")]
+    fn test_no_default_headers(server: MockServer, #[case] args: Vec<&str>, #[case] config: &str) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [tasks.get.headers]
+                X-Declared = "declared"
+
+                [config]
+                {}
+            "#,
+            server.address(),
+            config,
+        );
+        let opt = Opt::try_parse_from([vec!["req", "-f", "-", "get"], args].concat()).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("x-declared", "declared")
+                .matches(|req| {
+                    req.headers.iter().flatten().all(|(k, _)| {
+                        ["host", "accept", "content-length", "x-declared"]
+                            .contains(&k.to_ascii_lowercase().as_str())
+                    })
+                });
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case("", "MyBot/1.0")]
+    #[case("headers.User-Agent = \"Explicit/2.0\"", "Explicit/2.0")]
+    fn test_user_agent(server: MockServer, #[case] task: &str, #[case] expected: &str) {
+        let input = format!(
+            r#"
+                [variables]
+                BOT = "MyBot"
+
+                [config]
+                user-agent = "${{BOT}}/1.0"
+
+                [tasks.get]
+                GET = "http://{}/get"
+                {}
+            "#,
+            server.address(),
+            task,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("user-agent", expected);
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_curl_with_user_agent() {
+        let input = r#"
+            [tasks.get]
+            GET = "https://example.com/get"
+
+            [tasks.get.config]
+            user-agent = "MyBot/1.0"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl -A 'MyBot/1.0' -X GET 'https://example.com/get'\n",
+        );
+    }
+
+    #[rstest]
+    #[case(vec![], "Bearer secret", "key")]
+    #[case(vec!["--redact"], "<redacted>", "<redacted>")]
+    fn test_curl_with_redact(#[case] args: Vec<&str>, #[case] auth: &str, #[case] key: &str) {
+        let input = r#"
+            [tasks.get]
+            GET = "https://example.com/get"
+            headers.Authorization = "Bearer secret"
+            headers.X-Api-Key = "key"
+            headers.X-Trace = "trace"
+
+            [tasks.get.config]
+            user-agent = "MyBot/1.0"
+            redact-headers = ["x-api-key"]
+        "#;
+        let opt =
+            Opt::try_parse_from([vec!["req", "-f", "-", "get", "--curl"], args].concat()).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "curl -A 'MyBot/1.0' -X GET 'https://example.com/get' \\\n\t-H 'authorization:{}' \\\n\t-H 'x-api-key:{}' \\\n\t-H 'x-trace:trace'\n",
+                auth, key
+            ),
+        );
+    }
+
+    #[rstest]
+    #[case(vec![], 200, "ok", ExitCode::SUCCESS)]
+    #[case(vec![], 404, "not found", ExitCode::FAILURE)]
+    #[case(vec!["--fail"], 200, "ok", ExitCode::SUCCESS)]
+    #[case(vec!["--fail"], 404, "", ExitCode::FAILURE)]
+    #[case(vec!["--fail", "-i"], 500, "", ExitCode::FAILURE)]
+    #[case(vec!["--quiet"], 200, "ok", ExitCode::SUCCESS)]
+    #[case(vec!["--no-progress", "-i"], 200, "ok", ExitCode::SUCCESS)]
+    #[case(vec!["--verbose", "--redact"], 200, "ok", ExitCode::SUCCESS)]
+    fn test_fail(
+        server: MockServer,
+        #[case] args: Vec<&str>,
+        #[case] status: u16,
+        #[case] expected: &str,
+        #[case] expected_code: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from([vec!["req", "-f", "-", "get"], args].concat()).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(status)
+                .body(if status == 200 { "ok" } else { "not found" });
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected_code);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(200, "ok", ExitCode::SUCCESS)]
+    #[case(200, "ng", ExitCode::from(3))]
+    #[case(500, "ok", ExitCode::FAILURE)]
+    fn test_expect_on_fail_exit(
+        server: MockServer,
+        #[case] status: u16,
+        #[case] body: &str,
+        #[case] expected: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [tasks.get.expect]
+                body-matches = "ok"
+                on-fail-exit = 3
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(status).body(body);
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+    }
+
+    #[rstest]
+    #[case(vec![], "from-file")]
+    #[case(vec!["-v", "NAME=from-var"], "from-var")]
+    #[case(vec!["-e", "/dev/null"], "from-variables")]
+    #[case(vec!["--no-env-file"], "from-variables")]
+    #[case(vec!["--no-env-file", "-v", "NAME=from-var"], "from-var")]
+    fn test_env_file(server: MockServer, #[case] args: Vec<&str>, #[case] expected: &str) {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join(".env"), "# comment\nexport NAME=\"from-file\"\n").unwrap();
+        let path = dir.join("req.toml");
+        fs::write(
+            &path,
+            format!(
+                r#"
+                    [variables]
+                    NAME = "from-variables"
+
+                    [config]
+                    env-file = ".env"
+
+                    [tasks.get]
+                    GET = "http://{}/get"
+                    queries.name = "${{NAME}}"
+                "#,
+                server.address(),
+            ),
+        )
+        .unwrap();
+        let opt =
+            Opt::try_parse_from([vec!["req", "-f", path.to_str().unwrap(), "get"], args].concat())
+                .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .query_param("name", expected);
+            then.status(200).body("ok");
+        });
+
+        let code = opt.exec(&mut std::io::empty(), &mut std::io::empty());
+        fs::remove_dir_all(&dir).unwrap();
+
+        mock.assert();
+        assert_eq!(code.unwrap(), ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_curl_with_client_cert() {
+        let input = r#"
+            [variables]
+            CERTS = "/etc/certs"
+
+            [tasks.get]
+            GET = "https://example.com/get"
+
+            [tasks.get.config]
+            client-cert = "${CERTS}/client.crt"
+            client-key = "${CERTS}/client.key"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl --cert '/etc/certs/client.crt' --key '/etc/certs/client.key' -X GET 'https://example.com/get'\n",
+        );
+    }
+
+    #[rstest]
+    #[case(vec!["--format", "yaml"], "tasks:\n  get:\n    GET: http://{}/get\n    queries:\n      q: '${Q}'\nvariables:\n  Q: query\n")]
+    #[case(vec!["--format", "json"], r#"{"tasks": {"get": {"GET": "http://{}/get", "queries": {"q": "${Q}"}}}, "variables": {"Q": "query"}}"#)]
+    #[case(vec![], r#"{"tasks": {"get": {"GET": "http://{}/get", "queries": {"q": "${Q}"}}}, "variables": {"Q": "query"}}"#)]
+    fn test_format(server: MockServer, #[case] args: Vec<&str>, #[case] input: &str) {
+        let input = input.replace("{}", &server.address().to_string());
+        let opt = Opt::try_parse_from([vec!["req", "-f", "-", "get"], args].concat()).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .query_param("q", "query");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_format_from_extension(server: MockServer) {
+        let path = std::env::temp_dir().join(format!("req-test-{}.yml", Uuid::new_v4()));
+        fs::write(
+            &path,
+            format!("tasks:\n  get:\n    GET: http://{}/get\n", server.address()),
+        )
+        .unwrap();
+        let opt = Opt::try_parse_from(vec!["req", "-f", path.to_str().unwrap(), "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("ok");
+        });
+
+        let code = opt.exec(&mut std::io::empty(), &mut std::io::empty());
+        fs::remove_file(&path).unwrap();
+
+        mock.assert();
+        assert_eq!(code.unwrap(), ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case("1.0", "--http1.0")]
+    #[case("1.1", "--http1.1")]
+    #[case("2", "--http2")]
+    #[case("2-prior-knowledge", "--http2-prior-knowledge")]
+    fn test_curl_with_http_version(#[case] version: &str, #[case] flag: &str) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "https://example.com/get"
+                config.http-version = "{}"
+            "#,
+            version,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("curl {} -X GET 'https://example.com/get'\n", flag),
+        );
+    }
+
+    #[rstest]
+    #[case("1.0")]
+    #[case("1.1")]
+    fn test_http_version(server: MockServer, #[case] version: &str) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+                config.http-version = "{}"
+            "#,
+            server.address(),
+            version,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case("", Method::GET)]
+    #[case("[config]\ndefault-method = \"post\"", Method::POST)]
+    #[case("[tasks.fetch.body.graphql]\nquery = \"{ viewer }\"", Method::POST)]
+    #[case(
+        "[tasks.fetch.body.graphql]\nquery = \"{ viewer }\"\n[config]\ndefault-method = \"put\"",
+        Method::PUT
+    )]
+    fn test_default_method(server: MockServer, #[case] config: &str, #[case] method: Method) {
+        let input = format!(
+            r#"
+                [tasks.fetch]
+                url = "http://{}/fetch"
+
+                {}
+            "#,
+            server.address(),
+            config,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "fetch"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(method).path("/fetch");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case(
+        "url = \"https://example.com\"\nPOST = \"https://example.com\"",
+        "duplicate definition of method and url"
+    )]
+    #[case(
+        "url = \"https://example.com\"\nconfig.default-method = \"FETCH\"",
+        "invalid method in default-method: FETCH"
+    )]
+    fn test_default_method_error(#[case] task: &str, #[case] expected: &str) {
+        let input = format!("[tasks.fetch]\n{}", task);
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "fetch"]).unwrap();
+
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).contains(expected), "{:#}", err);
+    }
+
+    #[rstest]
+    #[case(vec![], "env-file = [\".env\", \".env.local\"]", "local", "base")]
+    #[case(vec![], "env-file = [\".env.local\", \".env\"]", "base", "base")]
+    #[case(vec!["-e", "{dir}/.env", "-e", "{dir}/.env.local"], "", "local", "base")]
+    #[case(vec!["-e", "{dir}/.env", "-e", "{dir}/.env.local", "-v", "HOST=cli"], "", "cli", "base")]
+    fn test_env_files_order(
+        server: MockServer,
+        #[case] args: Vec<&str>,
+        #[case] config: &str,
+        #[case] host: &str,
+        #[case] port: &str,
+    ) {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join(".env"), "HOST=base\nPORT=base\n").unwrap();
+        fs::write(dir.join(".env.local"), "HOST=local\n").unwrap();
+        let path = dir.join("req.toml");
+        fs::write(
+            &path,
+            format!(
+                r#"
+                    [config]
+                    {}
+
+                    [tasks.get]
+                    GET = "http://{}/get"
+                    queries = {{ host = "${{HOST}}", port = "${{PORT}}" }}
+                "#,
+                config,
+                server.address(),
+            ),
+        )
+        .unwrap();
+        let dir_str = dir.to_str().unwrap();
+        let args: Vec<String> = args.iter().map(|a| a.replace("{dir}", dir_str)).collect();
+        let opt = Opt::try_parse_from(
+            ["req", "-f", path.to_str().unwrap(), "get"]
+                .iter()
+                .map(|s| s.to_string())
+                .chain(args),
+        )
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .query_param("host", host)
+                .query_param("port", port);
+            then.status(200).body("ok");
+        });
+
+        let code = opt.exec(&mut std::io::empty(), &mut std::io::empty());
+        fs::remove_dir_all(&dir).unwrap();
+
+        mock.assert();
+        assert_eq!(code.unwrap(), ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_meta_out(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        let body_path = dir.join("body.bin");
+        let meta_path = dir.join("meta.json");
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "get",
+            "--out",
+            body_path.to_str().unwrap(),
+            "--meta-out",
+            meta_path.to_str().unwrap(),
+        ])
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(201)
+                .header("x-cache", "HIT")
+                .header("set-cookie", "a=1")
+                .header("set-cookie", "b=2")
+                .body("body");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(fs::read_to_string(&body_path).unwrap(), "body");
+        let meta: serde_json::Value =
+            serde_json::from_slice(&fs::read(&meta_path).unwrap()).unwrap();
+        assert_eq!(meta["status"], 201);
+        assert_eq!(meta["headers"]["x-cache"], "HIT");
+        assert_eq!(meta["headers"]["set-cookie"], json!(["a=1", "b=2"]));
+        assert_eq!(meta["url"], server.url("/get"));
+        assert!(meta["elapsed_ms"].is_u64());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rstest]
+    #[case(vec!["--require-header", "x-cache"], ExitCode::SUCCESS)]
+    #[case(vec!["--require-header", "X-Cache", "--require-header", "etag"], ExitCode::SUCCESS)]
+    #[case(vec!["--require-header", "x-cache", "--require-header", "x-missing"], ExitCode::FAILURE)]
+    fn test_require_header(
+        server: MockServer,
+        #[case] args: Vec<&str>,
+        #[case] expected: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from([vec!["req", "-f", "-", "get"], args].concat()).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200)
+                .header("x-cache", "HIT")
+                .header("etag", "\"abc\"")
+                .body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+    }
+
+    #[rstest]
+    #[case(vec![], ExitCode::FAILURE)]
+    #[case(vec!["--diff-ignore", ".meta.timestamp,.id"], ExitCode::SUCCESS)]
+    #[case(vec!["--diff-ignore", ".meta.timestamp"], ExitCode::FAILURE)]
+    fn test_snapshot_diff_ignore(
+        server: MockServer,
+        #[case] args: Vec<&str>,
+        #[case] expected: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let snapshot = std::env::temp_dir().join(format!("req-test-{}.json", Uuid::new_v4()));
+        let path = snapshot.to_str().unwrap();
+        fs::write(
+            &snapshot,
+            r#"{"id": 1, "name": "alice", "meta": {"timestamp": "2024-01-01"}}"#,
+        )
+        .unwrap();
+        let opt =
+            Opt::try_parse_from([vec!["req", "-f", "-", "get", "--snapshot", path], args].concat())
+                .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).json_body(
+                json!({ "id": 2, "name": "alice", "meta": { "timestamp": "2024-06-30" } }),
+            );
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+        fs::remove_file(snapshot).unwrap();
+    }
+
+    #[rstest]
+    #[case("<p>\n  hello   world\n</p>\n", vec![], ExitCode::FAILURE)]
+    #[case("<p>\n  hello   world\n</p>\n", vec!["--ignore-whitespace"], ExitCode::SUCCESS)]
+    #[case("<p>\n\n\thello world  \n</p>", vec!["--ignore-whitespace"], ExitCode::SUCCESS)]
+    #[case("<p>\n  hello   there\n</p>\n", vec!["--ignore-whitespace"], ExitCode::FAILURE)]
+    #[case("<p>hello world</p>\n", vec!["--ignore-whitespace"], ExitCode::FAILURE)]
+    fn test_snapshot_ignore_whitespace(
+        server: MockServer,
+        #[case] body: &str,
+        #[case] args: Vec<&str>,
+        #[case] expected: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let snapshot = std::env::temp_dir().join(format!("req-test-{}.html", Uuid::new_v4()));
+        let path = snapshot.to_str().unwrap();
+        fs::write(&snapshot, "<p>\nhello world\n</p>\n").unwrap();
+        let opt =
+            Opt::try_parse_from([vec!["req", "-f", "-", "get", "--snapshot", path], args].concat())
+                .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body(body);
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+        fs::remove_file(snapshot).unwrap();
+    }
+
+    #[rstest]
+    fn test_snapshot_changed_field_fails(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let snapshot = std::env::temp_dir().join(format!("req-test-{}.json", Uuid::new_v4()));
+        let path = snapshot.to_str().unwrap();
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "get",
+            "--snapshot",
+            path,
+            "--diff-ignore",
+            ".id",
+        ])
+        .unwrap();
+        let mut mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200)
+                .json_body(json!({ "id": 1, "name": "alice" }));
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(snapshot.exists());
+
+        mock.delete();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200)
+                .json_body(json!({ "id": 2, "name": "bob" }));
+        });
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::FAILURE);
+        fs::remove_file(snapshot).unwrap();
+    }
+
+    #[rstest]
+    fn test_global_headers(server: MockServer) {
+        let input = format!(
+            r#"
+                [variables]
+                VERSION = "2"
+
+                [headers]
+                Accept = "application/json"
+                X-Api-Version = "${{VERSION}}"
+                X-Tag = ["a", "b"]
+
+                [tasks.get]
+                GET = "http://{}/get"
+                headers.x-tag = "task"
+                config.timeout = 5
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("accept", "application/json")
+                .header("x-api-version", "2")
+                .header("x-tag", "task")
+                .matches(|req| {
+                    let tags = req.headers.iter().flatten();
+                    tags.filter(|(k, _)| k.eq_ignore_ascii_case("x-tag"))
+                        .count()
+                        == 1
+                });
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_curl_with_compressed_body() {
+        let input = r#"
+            [tasks.post]
+            POST = "https://example.com/post"
+            body.plain = "hello"
+
+            [tasks.post.config]
+            compress = "deflate"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("-H 'content-encoding:deflate'"), "{}", out);
+        assert!(
+            out.starts_with("base64 -d << REQUEST_BODY | curl"),
+            "{}",
+            out
+        );
+        let encoded = out
+            .split("--data-binary @-\n")
+            .nth(1)
+            .and_then(|s| s.strip_suffix("\nREQUEST_BODY\n"))
+            .unwrap();
+        let compressed =
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded).unwrap();
+        let mut body = String::new();
+        flate2::read::ZlibDecoder::new(compressed.as_slice())
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "hello");
+    }
+
+    #[rstest]
+    fn test_curl_with_timeout() {
+        let input = r#"
+            [tasks.get]
+            GET = "https://example.com/get"
+
+            [tasks.get.config]
+            timeout = "1500ms"
+            connect-timeout = 5
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl --max-time 1.5 --connect-timeout 5 -X GET 'https://example.com/get'\n",
+        );
+    }
+
+    #[rstest]
+    #[case("get", "curl -X GET 'https://example.com/get' \\\n\t-H 'accept:application/json' \\\n\t-H 'x-token:secret'\n")]
+    #[case("override", "curl -X GET 'https://example.com/override' \\\n\t-H 'x-token:secret' \\\n\t-H 'accept:text/plain'\n")]
+    fn test_curl_with_config_headers(#[case] name: &str, #[case] expected: &str) {
+        let input = r#"
+            [variables]
+            TOKEN = "secret"
+
+            [config.headers]
+            Accept = "application/json"
+            X-Token = "${TOKEN}"
+
+            [tasks.get]
+            GET = "https://example.com/get"
+
+            [tasks.override]
+            GET = "https://example.com/override"
+            headers.accept = "text/plain"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", name, "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn test_config_headers(server: MockServer) {
+        let input = format!(
+            r#"
+                [config.headers]
+                X-Token = "secret"
+
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("x-token", "secret");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case(vec![], "[{\"items\":[1,2],\"meta\":{\"next_cursor\":\"abc\"}},{\"items\":[3],\"meta\":{\"next_cursor\":\"\"}}]\n")]
+    #[case(vec!["--ndjson"], "{\"items\":[1,2],\"meta\":{\"next_cursor\":\"abc\"}}\n{\"items\":[3],\"meta\":{\"next_cursor\":\"\"}}\n")]
+    #[case(vec!["--max-pages", "1"], "[{\"items\":[1,2],\"meta\":{\"next_cursor\":\"abc\"}}]\n")]
+    fn test_paginate(server: MockServer, #[case] args: Vec<&str>, #[case] expected: &str) {
+        let input = format!(
+            r#"
+                [tasks.list]
+                GET = "http://{}/list"
+            "#,
+            server.address(),
+        );
+        let mut argv = vec![
+            "req",
+            "-f",
+            "-",
+            "list",
+            "--paginate",
+            "next=.meta.next_cursor",
+        ];
+        argv.extend(args);
+        let opt = Opt::try_parse_from(argv).unwrap();
+        let first = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/list")
+                .matches(|req| req.query_params.as_ref().is_none_or(|q| q.is_empty()));
+            then.status(200)
+                .json_body(json!({ "items": [1, 2], "meta": { "next_cursor": "abc" } }));
+        });
+        let second = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/list")
+                .query_param("cursor", "abc");
+            then.status(200)
+                .json_body(json!({ "items": [3], "meta": { "next_cursor": "" } }));
+        });
+
+        let mut buf = vec![];
+        let code = opt.exec(&mut input.as_bytes(), &mut buf).unwrap();
+
+        first.assert();
+        second.assert_hits(if expected.contains("[3]") { 1 } else { 0 });
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(buf).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn test_paginate_invalid_arg() {
+        assert!(Opt::try_parse_from(vec!["req", "list", "--paginate", ".meta.next"]).is_err());
+    }
+
+    #[rstest]
+    fn test_post_with_gzip(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.post]
+                POST = "http://{}/post"
+                body.json = {{ foo = "FOO" }}
+
+                [tasks.post.config]
+                compress = "gzip"
+                compression-level = 9
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post")
+                .header("content-encoding", "gzip")
+                .header("content-type", "application/json")
+                .matches(|req| {
+                    let mut body = String::new();
+                    flate2::read::GzDecoder::new(req.body.as_deref().unwrap_or_default())
+                        .read_to_string(&mut body)
+                        .is_ok()
+                        && body == r#"{"foo":"FOO"}"#
+                });
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case(vec![], true)]
+    #[case(vec!["--warn-vars"], true)]
+    #[case(vec!["--strict-vars"], false)]
+    #[case(vec!["--strict-vars", "-v", "TOKEN=token"], true)]
+    fn test_default_value(server: MockServer, #[case] args: Vec<&str>, #[case] ok: bool) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+                headers.authorization = "${{TOKEN:-anonymous}}"
+            "#,
+            server.address(),
+        );
+        let mut argv = vec!["req", "-f", "-", "get"];
+        argv.extend(args);
+        let opt = Opt::try_parse_from(argv).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("ok");
+        });
+
+        let result = opt.exec(&mut input.as_bytes(), &mut std::io::empty());
+
+        if ok {
+            assert_eq!(result.unwrap(), ExitCode::SUCCESS);
+            mock.assert();
+        } else {
+            let err = format!("{:#}", result.unwrap_err());
+            assert!(err.contains("default \"anonymous\" is used"), "{}", err);
+            mock.assert_hits(0);
+        }
+    }
+
+    #[rstest]
+    fn test_options_asterisk_form() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = vec![];
+            let mut chunk = [0; 1024];
+            while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut chunk).unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nAllow: GET, OPTIONS\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nfoo\r\n0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(buf).unwrap()
+        });
+        let input = format!(
+            r#"
+                [tasks.options]
+                OPTIONS = "http://{}/*"
+                headers.x-foo = "FOO"
+            "#,
+            addr,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "options"]).unwrap();
+
+        let mut buf = vec![];
+        let code = opt.exec(&mut input.as_bytes(), &mut buf).unwrap();
+        let request = handle.join().unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(request.starts_with(&format!("OPTIONS * HTTP/1.1\r\nHost: {}\r\n", addr)));
+        assert!(request.contains("x-foo: FOO\r\n"));
+        assert_eq!(String::from_utf8(buf).unwrap(), "foo");
+    }
+
+    #[rstest]
+    fn test_curl_options_asterisk_form() {
+        let input = r#"
+            [tasks.options]
+            OPTIONS = "http://example.com/*"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "options", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl --request-target '*' -X OPTIONS 'http://example.com/'\n",
+        );
+    }
+
+    #[rstest]
+    fn test_options_asterisk_form_over_https() {
+        let input = r#"
+            [tasks.options]
+            OPTIONS = "https://example.com/*"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "options"]).unwrap();
+
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("supported only over http"));
+    }
+
+    #[rstest]
+    #[case(vec![], "task")]
+    #[case(vec!["--out"], "cli")]
+    fn test_task_output(server: MockServer, #[case] args: Vec<&str>, #[case] used: &str) {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        let input = format!(
+            r#"
+                [variables]
+                DIR = "{}"
+
+                [tasks.report]
+                GET = "http://{}/report"
+                output = "${{DIR}}/task.txt"
+            "#,
+            dir.display(),
+            server.address(),
+        );
+        let cli_output = dir.join("cli.txt");
+        let mut argv = vec!["req", "-f", "-", "report"];
+        for arg in args {
+            argv.extend([arg, cli_output.to_str().unwrap()]);
+        }
+        let opt = Opt::try_parse_from(argv).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/report");
+            then.status(200).body("report");
+        });
+
+        let mut buf = vec![];
+        let code = opt.exec(&mut input.as_bytes(), &mut buf).unwrap();
+        let written = fs::read_to_string(dir.join(format!("{}.txt", used))).unwrap();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(written, "report");
+        assert_eq!(files, 1);
+        assert!(buf.is_empty());
+    }
+
+    #[rstest]
+    #[case("", "ja,en;q=0.8")]
+    #[case("headers.Accept-Language = \"fr\"", "fr")]
+    fn test_accept_language(server: MockServer, #[case] headers: &str, #[case] expected: &str) {
+        let input = format!(
+            r#"
+                [variables]
+                LANG = "ja"
+
+                [tasks.get]
+                GET = "http://{}/get"
+                config.accept-language = "${{LANG}},en;q=0.8"
+                {}
+            "#,
+            server.address(),
+            headers,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("accept-language", expected);
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_dump_request_and_replay(server: MockServer) {
+        let path = std::env::temp_dir().join(format!("req-test-{}.json", Uuid::new_v4()));
+        let input = format!(
+            r#"
+                [tasks.post]
+                POST = "http://{}/post"
+                queries.q = "query"
+                headers.x-foo = "FOO"
+                body.json = {{ foo = "FOO" }}
+            "#,
+            server.address(),
+        );
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post")
+                .query_param("q", "query")
+                .header("x-foo", "FOO")
+                .header("content-type", "application/json")
+                .header("user-agent", concat!("req/", env!("CARGO_PKG_VERSION")))
+                .json_body(json!({ "foo": "FOO" }));
+            then.status(200).body("ok");
+        });
+
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "post",
+            "--dump-request",
+            path.to_str().unwrap(),
+        ])
+        .unwrap();
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+        assert_eq!(code, ExitCode::SUCCESS);
+
+        let opt = Opt::try_parse_from(vec!["req", "--replay", path.to_str().unwrap()]).unwrap();
+        let mut buf = vec![];
+        let code = opt.exec(&mut std::io::empty(), &mut buf).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        mock.assert_hits(2);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(buf).unwrap(), "ok");
+    }
+
+    #[rstest]
+    fn test_cookies(server: MockServer) {
+        let input = format!(
+            r#"
+                [variables]
+                SESSION = "abc"
+
+                [tasks.get]
+                GET = "http://{}/get"
+                headers.cookie = "theme=dark"
+
+                [tasks.get.cookies]
+                session = "${{SESSION}}"
+                lang = "ja"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("cookie", "theme=dark; lang=ja; session=abc");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_curl_with_cookies() {
+        let input = r#"
+            [tasks.get]
+            GET = "https://example.com/get"
+            cookies = { session = "abc", lang = "ja" }
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl -X GET 'https://example.com/get' \\\n\t-b 'lang=ja; session=abc'\n",
+        );
+    }
+
+    #[rstest]
+    #[case("v1\nitems\n", true)]
+    #[case("v1\r\nitems\r\n", true)]
+    #[case("v1\n", false)]
+    fn test_interactive(server: MockServer, #[case] answers: &str, #[case] ok: bool) {
+        let path = std::env::temp_dir().join(format!("req-test-{}.toml", Uuid::new_v4()));
+        let input = format!(
+            r#"
+                [variables]
+                URL = "http://{}/${{VERSION}}"
+
+                [tasks.get]
+                GET = "${{URL}}/${{RESOURCE}}"
+            "#,
+            server.address(),
+        );
+        fs::write(&path, input).unwrap();
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            path.to_str().unwrap(),
+            "get",
+            "--interactive",
+        ])
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/v1/items");
+            then.status(200).body("ok");
+        });
+
+        let res = opt.exec(&mut answers.as_bytes(), &mut std::io::empty());
+        fs::remove_file(&path).unwrap();
+
+        if ok {
+            assert_eq!(res.unwrap(), ExitCode::SUCCESS);
+            mock.assert();
+        } else {
+            assert!(format!("{:#}", res.unwrap_err()).contains("aborted"));
+            mock.assert_hits(0);
+        }
+    }
+
+    #[rstest]
+    #[case(
+        "body.form = { foo = \"FOO\" }",
+        "application/x-www-form-urlencoded; charset=utf-8",
+        "foo=FOO"
+    )]
+    #[case(
+        "body.json = { foo = \"FOO\" }",
+        "application/vnd.api+json",
+        "{\"foo\":\"FOO\"}"
+    )]
+    #[case("body.plain = \"foo\"", "text/plain", "foo")]
+    fn test_explicit_content_type(
+        server: MockServer,
+        #[case] body: &str,
+        #[case] content_type: &str,
+        #[case] expected: &str,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.post]
+                POST = "http://{}/post"
+                headers.Content-Type = "{}"
+                {}
+            "#,
+            server.address(),
+            content_type,
+            body,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post")
+                .matches(|req| {
+                    req.headers.as_ref().is_some_and(|headers| {
+                        headers
+                            .iter()
+                            .filter(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+                            .count()
+                            == 1
+                    })
+                })
+                .header("content-type", content_type)
+                .body(expected);
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_explicit_content_type_with_multipart() {
+        let input = r#"
+            [tasks.post]
+            POST = "http://localhost/post"
+            headers.Content-Type = "multipart/form-data"
+            body.multipart = { foo = "FOO" }
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post", "--curl"]).unwrap();
+
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("body.multipart"));
+    }
+
+    #[rstest]
+    fn test_include(server: MockServer) {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib/common.toml"),
+            format!(
+                r#"
+                    include = ["base.toml"]
+
+                    [variables]
+                    HOST = "{}"
+                    NAME = "common"
+
+                    [tasks.common]
+                    GET = "http://${{HOST}}/common/${{NAME}}"
+                "#,
+                server.address(),
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lib/base.toml"),
+            r#"
+                [tasks.base]
+                GET = "http://${HOST}/base/${NAME}"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("main.toml"),
+            r#"
+                include = ["lib/common.toml"]
+
+                [variables]
+                NAME = "main"
+            "#,
+        )
+        .unwrap();
+        let main = dir.join("main.toml");
+        let common = server.mock(|when, then| {
+            when.method(Method::GET).path("/common/main");
+            then.status(200).body("common");
+        });
+        let base = server.mock(|when, then| {
+            when.method(Method::GET).path("/base/main");
+            then.status(200).body("base");
+        });
+
+        let mut outputs = vec![];
+        for name in ["common", "base"] {
+            let opt = Opt::try_parse_from(vec!["req", "-f", main.to_str().unwrap(), name]).unwrap();
+            let mut buf = vec![];
+            let code = opt.exec(&mut std::io::empty(), &mut buf).unwrap();
+            assert_eq!(code, ExitCode::SUCCESS);
+            outputs.push(String::from_utf8(buf).unwrap());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        common.assert();
+        base.assert();
+        assert_eq!(outputs, vec!["common", "base"]);
+    }
+
+    #[rstest]
+    fn test_circular_include() {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("a.toml"), "include = [\"b.toml\"]").unwrap();
+        fs::write(dir.join("b.toml"), "include = [\"a.toml\"]").unwrap();
+        let path = dir.join("a.toml");
+        let opt = Opt::try_parse_from(vec!["req", "-f", path.to_str().unwrap(), "task"]).unwrap();
+
+        let err = opt
+            .exec(&mut std::io::empty(), &mut std::io::empty())
+            .unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(format!("{:#}", err).contains("circular include"));
+    }
+
+    #[rstest]
+    fn test_repeat(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--repeat", "3", "--summary"])
+            .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("ok");
+        });
+
+        let mut buf = vec![];
+        let code = opt.exec(&mut input.as_bytes(), &mut buf).unwrap();
+
+        mock.assert_hits(3);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(buf).unwrap(), "okokok");
+    }
+
+    #[rstest]
+    fn test_json_lines(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "get",
+            "--repeat",
+            "3",
+            "--json-lines",
+        ])
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(201).body("body is not written");
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert_hits(3);
+        assert_eq!(code, ExitCode::SUCCESS);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(line["iter"], i + 1);
+            assert_eq!(line["status"], 201);
+            assert!(line["ms"].is_u64());
+        }
+    }
+
+    #[rstest]
+    fn test_summary() {
+        let mut summary = Summary::default();
+        for (success, millis, bytes) in [(true, 10, 2), (false, 30, 5), (true, 20, 2)] {
+            summary.record(&Attempt {
+                success,
+                expect_failed: false,
+                status: if success { 200 } else { 500 },
+                bytes,
+                timing: Timing {
+                    headers: Duration::ZERO,
+                    first_byte: None,
+                    total: Duration::from_millis(millis),
+                },
+            });
+        }
+
+        assert_eq!(
+            summary.to_string(),
+            "requests  3\n\
+             success   2\n\
+             failure   1\n\
+             latency   min 10.00ms / max 30.00ms / avg 20.00ms\n\
+             bytes     9\n",
+        );
+    }
+
+    #[rstest]
+    fn test_proxy_per_scheme(server: MockServer) {
+        let proxy = MockServer::start();
+        let input = format!(
+            r#"
+                [config.proxy]
+                http = "http://{proxy}"
+                https = "http://127.0.0.1:1"
+                no-proxy = ["127.0.0.1", "localhost"]
+
+                [tasks.proxied]
+                GET = "http://example.invalid/get"
+
+                [tasks.bypassed]
+                GET = "http://{server}/get"
+            "#,
+            proxy = proxy.address(),
+            server = server.address(),
+        );
+        let proxied = proxy.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("host", "example.invalid");
+            then.status(200).body("proxied");
+        });
+        let direct = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("direct");
+        });
+
+        let mut outputs = vec![];
+        for name in ["proxied", "bypassed"] {
+            let opt = Opt::try_parse_from(vec!["req", "-f", "-", name]).unwrap();
+            let mut buf = vec![];
+            let code = opt.exec(&mut input.as_bytes(), &mut buf).unwrap();
+            assert_eq!(code, ExitCode::SUCCESS);
+            outputs.push(String::from_utf8(buf).unwrap());
+        }
+
+        proxied.assert();
+        direct.assert();
+        assert_eq!(outputs, vec!["proxied", "direct"]);
+    }
+
+    #[rstest]
+    #[case(
+        "path = { id = \"${ID}\", postId = \"42\" }",
+        Some("/users/a%2Fb%20c/posts/42")
+    )]
+    #[case("path = { id = \"${ID}\" }", None)]
+    fn test_path_params(server: MockServer, #[case] path: &str, #[case] expected: Option<&str>) {
+        let input = format!(
+            r#"
+                [variables]
+                ID = "a/b c"
+
+                [tasks.posts]
+                GET = "http://{}/users/{{id}}/posts/{{postId}}"
+                {}
+            "#,
+            server.address(),
+            path,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "posts"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path(expected.unwrap_or("/users/"));
+            then.status(200).body("ok");
+        });
+
+        let res = opt.exec(&mut input.as_bytes(), &mut std::io::empty());
+
+        match expected {
+            Some(_) => {
+                assert_eq!(res.unwrap(), ExitCode::SUCCESS);
+                mock.assert();
+            }
+            None => {
+                let err = res.unwrap_err();
+                assert!(
+                    format!("{:#}", err)
+                        .contains("placeholder `{postId}` in URL is not defined in path"),
+                    "{:#}",
+                    err
+                );
+                mock.assert_hits(0);
+            }
+        }
+    }
+
+    #[rstest]
+    fn test_no_proxy_tags(server: MockServer) {
+        let proxy = MockServer::start();
+        let input = format!(
+            r#"
+                [config]
+                proxy = "http://{proxy}"
+                no-proxy-tags = ["internal"]
+
+                [tasks.proxied]
+                GET = "http://example.invalid/get"
+                tags = ["external"]
+
+                [tasks.bypassed]
+                GET = "http://{server}/get"
+                tags = ["internal"]
+            "#,
+            proxy = proxy.address(),
+            server = server.address(),
+        );
+        let proxied = proxy.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("host", "example.invalid");
+            then.status(200).body("proxied");
+        });
+        let direct = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("direct");
+        });
+
+        let mut outputs = vec![];
+        for name in ["proxied", "bypassed"] {
+            let opt = Opt::try_parse_from(vec!["req", "-f", "-", name]).unwrap();
+            let mut buf = vec![];
+            let code = opt.exec(&mut input.as_bytes(), &mut buf).unwrap();
+            assert_eq!(code, ExitCode::SUCCESS);
+            outputs.push(String::from_utf8(buf).unwrap());
+        }
+
+        proxied.assert();
+        direct.assert();
+        assert_eq!(outputs, vec!["proxied", "direct"]);
+    }
+
+    #[rstest]
+    #[case("token = \"${TOKEN}\"", "Bearer secret")]
+    #[case("header = \"Custom ${TOKEN}\"", "Custom secret")]
+    fn test_proxy_auth(#[case] auth: &str, #[case] expected: &str) {
+        let proxy = MockServer::start();
+        let input = format!(
+            r#"
+                [variables]
+                TOKEN = "secret"
+
+                [config.proxy]
+                http = "http://{}"
+                {}
+
+                [tasks.proxied]
+                GET = "http://example.invalid/get"
+            "#,
+            proxy.address(),
+            auth,
+        );
+        let mock = proxy.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("proxy-authorization", expected);
+            then.status(200).body("proxied");
+        });
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "proxied"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out, b"proxied");
+    }
+
+    #[rstest]
+    #[case(
+        "proxy = \"http://proxy:8080\"",
+        "https://example.com/",
+        " -x 'http://proxy:8080'"
+    )]
+    #[case(
+        "proxy = { http = \"http://a:8080\", https = \"http://b:8080\", no-proxy = [\"internal\"] }",
+        "https://example.com/",
+        " -x 'http://b:8080' --noproxy 'internal'"
+    )]
+    #[case(
+        "proxy = { https = \"http://b:8080\", token = \"t\" }",
+        "https://example.com/",
+        " -x 'http://b:8080' --proxy-header 'Proxy-Authorization: Bearer t'"
+    )]
+    fn test_curl_with_proxy(#[case] proxy: &str, #[case] url: &str, #[case] flags: &str) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "{}"
+                config.{}
+            "#,
+            url, proxy,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("curl{} -X GET '{}'\n", flags, url),
+        );
+    }
+
+    #[rstest]
+    fn test_print_url() {
+        let input = r#"
+            [variables]
+            HOST = "example.com"
+            QUERY = "hello world"
+
+            [tasks.search]
+            GET = "https://${HOST}/search"
+            queries.q = "${QUERY}"
+            queries.tag = ["a", "b"]
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "search", "--print-url"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "https://example.com/search?q=hello+world&tag=a&tag=b\n",
+        );
+    }
+
+    #[rstest]
+    #[case(
+        r#"query-raw = "b=2&a=1&weird=%20""#,
+        "https://example.com/search?b=2&a=1&weird=%20"
+    )]
+    #[case(
+        r#"queries.q = "x y"
+            query-raw = "?b=${B}&a=1""#,
+        "https://example.com/search?q=x+y&b=%2F&a=1"
+    )]
+    #[case(r#"query-raw = """#, "https://example.com/search")]
+    fn test_query_raw(#[case] query: &str, #[case] expected: &str) {
+        let input = format!(
+            r#"
+            variables.B = "%2F"
+
+            [tasks.search]
+            GET = "https://example.com/search"
+            {}
+        "#,
+            query
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "search", "--print-url"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", expected));
+    }
+
+    #[rstest]
+    #[case(vec!["--timeout", "200ms"], "timeout = 10")]
+    #[case(vec![], "timeout = \"${TIMEOUT}\"")]
+    fn test_timeout(server: MockServer, #[case] args: Vec<&str>, #[case] config: &str) {
+        let input = format!(
+            r#"
+                [variables]
+                TIMEOUT = "200ms"
+
+                [tasks.slow]
+                GET = "http://{}/slow"
+
+                [tasks.slow.config]
+                {}
+            "#,
+            server.address(),
+            config,
+        );
+        let mut argv = vec!["req", "-f", "-", "slow"];
+        argv.extend(args);
+        let opt = Opt::try_parse_from(argv).unwrap();
+        server.mock(|when, then| {
+            when.method(Method::GET).path("/slow");
+            then.status(200)
+                .body("ok")
+                .delay(std::time::Duration::from_secs(2));
+        });
+
+        let start = Instant::now();
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(
+            format!("{:#}", err).contains("task `slow` timed out"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[rstest]
+    fn test_json_with_file_refs(server: MockServer) {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("certs")).unwrap();
+        fs::write(dir.join("certs/cert.pem"), "-----BEGIN CERTIFICATE-----").unwrap();
+        fs::write(dir.join("certs/key.bin"), [0u8, 159, 146, 150]).unwrap();
+        fs::write(
+            dir.join("req.toml"),
+            format!(
+                r#"
+                    [variables]
+                    CERTS = "certs"
+
+                    [tasks.upload]
+                    POST = "http://{}/upload"
+
+                    [tasks.upload.body.json]
+                    cert = "@${{CERTS}}/cert.pem"
+                    key = "@base64:certs/key.bin"
+                    handle = "@@user"
+                    nested = [{{ cert = "@certs/cert.pem" }}]
+                "#,
+                server.address(),
+            ),
+        )
+        .unwrap();
+        let path = dir.join("req.toml");
+        let opt = Opt::try_parse_from(vec!["req", "-f", path.to_str().unwrap(), "upload"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/upload").json_body(json!({
+                "cert": "-----BEGIN CERTIFICATE-----",
+                "key": "AJ+Slg==",
+                "handle": "@user",
+                "nested": [{ "cert": "-----BEGIN CERTIFICATE-----" }],
+            }));
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut std::io::empty(), &mut std::io::empty())
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_json_interpolated_file_refs_are_verbatim(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.upload]
+                POST = "http://{}/upload"
+                body.json = {{ value = "${{x}}", values = ["${{x}}"] }}
+            "#,
+            server.address(),
+        );
+        let opt =
+            Opt::try_parse_from(vec!["req", "-f", "-", "upload", "-v", "x=@/etc/passwd"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/upload").json_body(json!({
+                "value": "@/etc/passwd",
+                "values": ["@/etc/passwd"],
+            }));
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case(r#"auth.bearer = "${TOKEN}""#, "Bearer t0ken")]
+    #[case(
+        r#"auth.basic = { username = "user", password = "${TOKEN}" }"#,
+        "Basic dXNlcjp0MGtlbg=="
+    )]
+    fn test_auth(server: MockServer, #[case] auth: &str, #[case] expected: &str) {
+        let input = format!(
+            r#"
+                [variables]
+                TOKEN = "t0ken"
+
+                [tasks.get]
+                GET = "http://{}/get"
+                {}
+            "#,
+            server.address(),
+            auth,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/get")
+                .header("authorization", expected);
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case(r#"{ key = "X-Api-Key", value = "${KEY}" }"#, "/get")]
+    #[case(
+        r#"{ key = "api_key", value = "${KEY}", location = "query" }"#,
+        "/get?api_key=s3cret"
+    )]
+    fn test_auth_api_key(server: MockServer, #[case] auth: &str, #[case] url: &str) {
+        let input = format!(
+            r#"
+                [variables]
+                KEY = "s3cret"
+
+                [tasks.get]
+                GET = "http://{}/get"
+                auth.api-key = {}
+            "#,
+            server.address(),
+            auth,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let mock = server.mock(|when, then| {
+            let when = when.method(Method::GET).path("/get");
+            if url.contains('?') {
+                when.query_param("api_key", "s3cret").matches(|req| {
+                    !req.headers
+                        .iter()
+                        .flatten()
+                        .any(|(k, _)| k.eq_ignore_ascii_case("x-api-key"))
+                });
+            } else {
+                when.header("x-api-key", "s3cret")
+                    .matches(|req| req.query_params.as_ref().is_none_or(|q| q.is_empty()));
+            }
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+        opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains(&format!("'http://{}{}'", server.address(), url)),
+            "{}",
+            out
+        );
+        assert_eq!(
+            out.contains("-H 'x-api-key:s3cret'"),
+            !url.contains('?'),
+            "{}",
+            out
+        );
+    }
+
+    const DIGEST_CHALLENGE: &str =
+        r#"Digest realm="req", qop="auth", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093""#;
+
+    fn is_valid_digest(req: &HttpMockRequest) -> bool {
+        let header = req
+            .headers
+            .iter()
+            .flatten()
+            .find(|(k, _)| k.eq_ignore_ascii_case("authorization"));
+        let Some((_, header)) = header else {
+            return false;
+        };
+        let Ok(answer) = digest_auth::AuthorizationHeader::parse(header) else {
+            return false;
+        };
+        let mut context = digest_auth::AuthContext::new_with_method(
+            "user",
+            "secret",
+            answer.uri.as_str(),
+            None::<&[u8]>,
+            digest_auth::HttpMethod::GET,
+        );
+        context.set_custom_cnonce(answer.cnonce.clone().unwrap_or_default());
+        let mut prompt = digest_auth::parse(DIGEST_CHALLENGE).unwrap();
+        answer.uri == "/private?page=1"
+            && prompt.respond(&context).unwrap().response == answer.response
+    }
+
+    #[rstest]
+    #[case("secret", ExitCode::SUCCESS, 2)]
+    #[case("wrong", ExitCode::FAILURE, 0)]
+    fn test_auth_digest(
+        server: MockServer,
+        #[case] password: &str,
+        #[case] expected: ExitCode,
+        #[case] hits: usize,
+    ) {
+        let input = format!(
+            r#"
+                [variables]
+                PASSWORD = "{}"
+
+                [tasks.get]
+                GET = "http://{}/private"
+                queries.page = "1"
+                auth.digest = {{ username = "user", password = "${{PASSWORD}}" }}
+            "#,
+            password,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let challenge = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/private")
+                .matches(|req| !is_valid_digest(req));
+            then.status(401)
+                .header("www-authenticate", DIGEST_CHALLENGE)
+                .body("unauthorized");
+        });
+        let ok = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/private")
+                .matches(is_valid_digest);
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        challenge.assert_hits(2 - hits / 2);
+        ok.assert_hits(hits / 2);
+        assert_eq!(code, expected);
+    }
+
+    #[rstest]
+    fn test_curl_with_digest() {
+        let input = r#"
+            [tasks.get]
+            GET = "https://example.com/get"
+            auth.digest = { username = "user", password = "it's" }
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl --digest -u 'user:it\\'s' -X GET 'https://example.com/get'\n",
+        );
+    }
+
+    #[rstest]
+    fn test_json_canonical(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.post]
+                POST = "http://{}/post"
+                config.json-canonical = true
+
+                [tasks.post.body.json]
+                timestamp = 1700000000
+                amount = {{ value = "10.00", currency = "JPY" }}
+                id = "order-1"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST)
+                .path("/post")
+                .header("content-type", "application/json")
+                .body(r#"{"amount":{"currency":"JPY","value":"10.00"},"id":"order-1","timestamp":1700000000}"#);
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case("cat > \"$REQ_TEST_OUT\"", ExitCode::SUCCESS, "{\"ok\":true}")]
+    #[case("cat > \"$REQ_TEST_OUT\"; exit 3", ExitCode::FAILURE, "{\"ok\":true}")]
+    #[cfg_attr(windows, ignore)]
+    fn test_exec(
+        server: MockServer,
+        #[case] cmd: &str,
+        #[case] expected: ExitCode,
+        #[case] piped: &str,
+    ) {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let path = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        std::env::set_var("REQ_TEST_OUT", &path);
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--exec", cmd]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("{\"ok\":true}");
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+        std::env::remove_var("REQ_TEST_OUT");
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected);
+        assert_eq!(written, piped);
+        assert!(out.is_empty());
+    }
+
+    #[rstest]
+    fn test_env_override(server: MockServer) {
+        let input = format!(
+            r#"
+                [variables]
+                HOST = "{}"
+                ENV = "from-file"
+
+                [tasks.get]
+                GET = "http://${{HOST}}/${{ENV}}"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "get",
+            "--env-override",
+            "ENV=from-override",
+            "-v",
+            "ENV=from-var",
+        ])
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/from-override");
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case(0, true)]
+    #[case(2000, false)]
+    fn test_local_timeout_per_redirect(
+        server: MockServer,
+        #[case] delay: u64,
+        #[case] success: bool,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/start"
+                headers.Authorization = "Bearer t0ken"
+                config.redirect = 5
+                config.timeout = 10
+                config.local-timeout-per-redirect = "300ms"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        server.mock(|when, then| {
+            when.method(Method::GET).path("/start");
+            then.status(302).header("location", "/hop");
+        });
+        server.mock(|when, then| {
+            when.method(Method::GET).path("/hop");
+            then.status(302)
+                .header("location", "/slow")
+                .delay(Duration::from_millis(200));
+        });
+        let slow = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/slow")
+                .header("authorization", "Bearer t0ken");
+            then.status(200)
+                .body("ok")
+                .delay(Duration::from_millis(delay));
+        });
+
+        let start = Instant::now();
+        let result = opt.exec(&mut input.as_bytes(), &mut std::io::empty());
+
+        slow.assert();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        match result {
+            Ok(code) => assert!(success && code == ExitCode::SUCCESS),
+            Err(err) => assert!(
+                !success && format!("{:#}", err).contains("task `get` timed out"),
+                "{:#}",
+                err
+            ),
+        }
+    }
+
+    #[rstest]
+    #[case(true)]
+    #[case(false)]
+    fn test_forward_auth_on_redirect(server: MockServer, #[case] forward: bool) {
+        let other = MockServer::start();
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/redirect"
+                headers.Authorization = "Bearer t0ken"
+                headers.Cookie = "session=abc"
+                config.redirect = 5
+                config.forward-auth-on-redirect = {}
+            "#,
+            server.address(),
+            forward,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+        let redirect = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/redirect")
+                .header("authorization", "Bearer t0ken");
+            then.status(302)
+                .header("location", format!("http://{}/target", other.address()));
+        });
+        let target = other.mock(|when, then| {
+            let when = when.method(Method::GET).path("/target").matches(|req| {
+                !req.headers
+                    .iter()
+                    .flatten()
+                    .any(|(k, _)| k.eq_ignore_ascii_case("cookie"))
+            });
+            if forward {
+                when.header("authorization", "Bearer t0ken");
+            } else {
+                when.matches(|req| {
+                    !req.headers
+                        .iter()
+                        .flatten()
+                        .any(|(k, _)| k.eq_ignore_ascii_case("authorization"))
+                });
+            }
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        redirect.assert();
+        target.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    #[case("GET", r#"config.retry = { count = 2, backoff = "10ms" }"#, &[], 3)]
+    #[case("GET", r#"config.retry = { count = 2, backoff = "10ms" }"#, &["--retry", "1"], 2)]
+    #[case("GET", r#"config.retry = { count = 2, backoff = "10ms", on = [500] }"#, &[], 1)]
+    #[case("POST", r#"config.retry = { count = 2, backoff = "10ms" }"#, &[], 1)]
+    #[case(
+        "POST",
+        r#"config = { retry = { count = 2, backoff = "10ms" }, retry-non-idempotent = true }"#,
+        &[],
+        3
+    )]
+    #[case("GET", "", &["--retry", "1"], 2)]
+    fn test_retry(
+        server: MockServer,
+        #[case] method: &str,
+        #[case] config: &str,
+        #[case] args: &[&str],
+        #[case] hits: usize,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.flaky]
+                {} = "http://{}/flaky"
+                {}
+            "#,
+            method,
+            server.address(),
+            config,
+        );
+        let mut argv = vec!["req", "-f", "-", "flaky"];
+        argv.extend(args);
+        let opt = Opt::try_parse_from(argv).unwrap();
+        let mock = server.mock(|when, then| {
+            when.path("/flaky");
+            then.status(503).body("unavailable");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert_hits(hits);
+        assert_eq!(code, ExitCode::FAILURE);
+    }
+
+    #[rstest]
+    fn test_retry_idempotency_key(server: MockServer) {
+        static KEYS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let input = format!(
+            r#"
+                [tasks.create]
+                POST = "http://{}/create"
+                config.retry = {{ count = 1, backoff = "10ms", idempotency-key = true }}
+            "#,
+            server.address(),
+        );
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/create").matches(|req| {
+                let key = req.headers.iter().flatten().find_map(|(k, v)| {
+                    k.eq_ignore_ascii_case("idempotency-key").then(|| v.clone())
+                });
+                KEYS.lock().unwrap().extend(key);
+                true
+            });
+            then.status(503);
+        });
+
+        for _ in 0..2 {
+            let opt = Opt::try_parse_from(vec!["req", "-f", "-", "create"]).unwrap();
+            let code = opt
+                .exec(&mut input.as_bytes(), &mut std::io::empty())
+                .unwrap();
+            assert_eq!(code, ExitCode::FAILURE);
+        }
+
+        mock.assert_hits(4);
+        let mut keys = KEYS.lock().unwrap().clone();
+        keys.dedup();
+        assert_eq!(keys.len(), 2, "{:?}", keys);
+    }
+
+    #[rstest]
+    fn test_timing() {
+        let timing = Timing {
+            headers: Duration::from_millis(12),
+            first_byte: Some(Duration::from_micros(12500)),
+            total: Duration::from_millis(20),
+        };
+        assert_eq!(
+            timing.to_string(),
+            "headers     12.00ms\nfirst-byte  12.50ms\ntotal       20.00ms\n"
+        );
+    }
+
+    #[rstest]
+    fn test_timing_keeps_body(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--timing"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("ok");
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out, b"ok");
+    }
+
+    #[rstest]
+    fn test_fan_out(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/default"
+            "#,
+            server.address(),
+        );
+        let slow = server.url("/slow");
+        let fast = server.url("/fast");
+        let missing = server.url("/missing");
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "get",
+            "--fan-out",
+            &slow,
+            "--fan-out",
+            &missing,
+            "--fan-out",
+            &fast,
+            "--concurrency",
+            "3",
+        ])
+        .unwrap();
+        let slow_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/slow");
+            then.status(200)
+                .delay(Duration::from_millis(300))
+                .body("slow\n");
+        });
+        let missing_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/missing");
+            then.status(404).body("missing\n");
+        });
+        let fast_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/fast");
+            then.status(200).body("fast\n");
+        });
+        let mut out = vec![];
+
+        let start = Instant::now();
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert!(start.elapsed() < Duration::from_millis(600));
+        slow_mock.assert();
+        missing_mock.assert();
+        fast_mock.assert();
+        assert_eq!(code, ExitCode::FAILURE);
+        assert_eq!(String::from_utf8(out).unwrap(), "slow\nmissing\nfast\n");
+    }
+
+    #[rstest]
+    fn test_lint() {
+        let input = r#"
+            [variables]
+            HOST = "example.com"
+            UNUSED = "unused"
+            NESTED = "v1"
+            BASE = "https://${HOST}/${NESTED}"
+
+            [tasks.get-with-body]
+            GET = "${BASE}/get"
+            body.plain = "ignored"
+
+            [tasks.json-without-type]
+            POST = "${BASE}/post"
+            body.plain = '{ "key": "value" }'
+
+            [tasks.json-with-type]
+            POST = "${BASE}/post"
+            headers.content-type = "application/json"
+            body.plain = '{ "key": "value" }'
+
+            [tasks.insecure]
+            GET = "${BASE}/get"
+            config = { insecure = true }
+
+            [tasks.no-redirect]
+            GET = "${BASE}/get"
+            config = { forward-auth-on-redirect = true }
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "lint"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::FAILURE);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "warning: task `get-with-body`: body is sent with GET\n\
+             warning: task `insecure`: `insecure = true` disables certificate verification\n\
+             warning: task `json-without-type`: body.plain looks like JSON but no content-type header is declared\n\
+             warning: task `no-redirect`: `forward-auth-on-redirect` has no effect with `redirect = 0`\n\
+             warning: variables: `UNUSED` is not used\n",
+        );
+    }
+
+    #[rstest]
+    fn test_lint_clean() {
+        let input = r#"
+            [variables]
+            HOST = "example.com"
+
+            [tasks.get]
+            GET = "https://${HOST}/get"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "lint"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(out.is_empty());
+    }
+
+    #[rstest]
+    #[case("payload.json", Some("application/json"))]
+    #[case("payload.dat", None)]
+    fn test_body_file(server: MockServer, #[case] name: &str, #[case] content_type: Option<&str>) {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, [b'{', 0, 159, b'}']).unwrap();
+        let input = format!(
+            r#"
+                [variables]
+                DIR = "{}"
+
+                [tasks.upload]
+                PUT = "http://{}/upload"
+                body.file = "${{DIR}}/{}"
+            "#,
+            dir.to_str().unwrap(),
+            server.address(),
+            name,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "upload"]).unwrap();
+        let mock = server.mock(|when, then| {
+            let when = when.method(Method::PUT).path("/upload");
+            match content_type {
+                Some(content_type) => {
+                    when.header("content-type", content_type);
+                }
+                None => {
+                    when.matches(|req| {
+                        !req.headers
+                            .iter()
+                            .flatten()
+                            .any(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+                    });
+                }
+            }
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+        let requests = mock.hits();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(requests, 1);
+        assert_eq!(code, ExitCode::SUCCESS);
     }
 
-    w.flush()?;
-    Ok(())
-}
+    #[rstest]
+    #[case("body.file = \"payload.txt\"", "from file")]
+    #[case("body.form.value.file = \"payload.txt\"", "value=from+file")]
+    fn test_files_relative_to_definition(
+        server: MockServer,
+        #[case] body: &str,
+        #[case] expected: &str,
+    ) {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("payload.txt"), "from file").unwrap();
+        fs::write(
+            dir.join("req.toml"),
+            format!(
+                "[tasks.upload]\nPUT = \"http://{}/upload\"\n{}",
+                server.address(),
+                body
+            ),
+        )
+        .unwrap();
+        let path = dir.join("req.toml");
+        let opt = Opt::try_parse_from(vec!["req", "-f", path.to_str().unwrap(), "upload"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::PUT).path("/upload").body(expected);
+            then.status(200).body("ok");
+        });
 
-fn print_header(res: &reqwest::blocking::Response) -> anyhow::Result<()> {
-    let out = stdout();
-    let mut out = BufWriter::new(out);
-    let status = res.status();
-    write!(out, "{:?} {}", res.version(), status.as_str())?;
-    if let Some(reason) = status.canonical_reason() {
-        writeln!(out, " {}", reason)?;
-    } else {
-        writeln!(out)?;
+        let code = opt
+            .exec(&mut std::io::empty(), &mut std::io::empty())
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
     }
-    for (key, val) in res.headers().iter() {
-        write!(out, "{}: ", key)?;
-        out.write(val.as_bytes())?;
-        writeln!(out)?;
+
+    #[rstest]
+    fn test_body_file_missing() {
+        let input = r#"
+            [tasks.upload]
+            PUT = "http://localhost/upload"
+            body.file = "/path/to/missing/payload.bin"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "upload"]).unwrap();
+
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        assert!(
+            format!("{:#}", err).contains("fail to read body file: /path/to/missing/payload.bin"),
+            "{:#}",
+            err
+        );
     }
-    writeln!(out)?;
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use httpmock::prelude::*;
-    use httpmock::Method;
-    use rstest::{fixture, rstest};
-    use serde_json::json;
-    use uuid::Uuid;
+    #[rstest]
+    fn test_curl_with_body_file() {
+        let input = r#"
+            [tasks.upload]
+            PUT = "https://example.com/upload"
+            body.file = "Cargo.toml"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "upload", "--curl"]).unwrap();
+        let mut out = vec![];
 
-    #[fixture]
-    fn server() -> MockServer {
-        MockServer::start()
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl -X PUT 'https://example.com/upload' \\\n\t--data-binary '@Cargo.toml'\n",
+        );
     }
 
     #[rstest]
-    #[case("get", Method::GET)]
-    #[case("post", Method::POST)]
-    #[case("put", Method::PUT)]
-    #[case("delete", Method::DELETE)]
-    #[case("head", Method::HEAD)]
-    #[case("options", Method::OPTIONS)]
-    #[case("patch", Method::PATCH)]
-    #[case("trace", Method::TRACE)]
-    fn test_method(server: MockServer, #[case] task: &str, #[case] method: Method) {
+    fn test_graphql(server: MockServer) {
         let input = format!(
             r#"
-                [tasks.{}]
-                {} = "http://{}/{}"
+                [variables]
+                USER = "octocat"
+
+                [tasks.user]
+                POST = "http://{}/graphql"
+
+                [tasks.user.body.graphql]
+                query = "query($login: String!) {{ user(login: $login) {{ name }} }}"
+                variables = {{ login = "${{USER}}", first = 10 }}
             "#,
-            task,
-            method,
             server.address(),
-            task,
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", task]).unwrap();
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "user"]).unwrap();
         let mock = server.mock(|when, then| {
-            when.method(method).path(format!("/{}", task));
+            when.method(Method::POST)
+                .path("/graphql")
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "query": "query($login: String!) { user(login: $login) { name } }",
+                    "variables": { "login": "octocat", "first": 10 },
+                }));
             then.status(200).body("ok");
         });
 
@@ -293,304 +5305,889 @@ mod tests {
     }
 
     #[rstest]
-    fn test_connect(server: MockServer) {
+    fn test_curl_with_graphql() {
+        let input = r#"
+            [tasks.viewer]
+            POST = "https://example.com/graphql"
+            body.graphql.query = "{ viewer { login } }"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "viewer", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl -X POST 'https://example.com/graphql' \\\n\
+             \t-H 'content-type:application/json' \\\n\
+             \t-d @- << REQUEST_BODY\n\
+             {\"query\":\"{ viewer { login } }\"}\n\
+             REQUEST_BODY\n",
+        );
+    }
+
+    #[rstest]
+    fn test_curl_with_xml() {
+        let input = r#"
+            [tasks.create]
+            POST = "https://example.com/items"
+            body.xml = "<item id='1'/>"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "create", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl -X POST 'https://example.com/items' \\\n\
+             \t-H 'content-type:application/xml' \\\n\
+             \t-d @- << REQUEST_BODY\n\
+             <item id='1'/>\n\
+             REQUEST_BODY\n",
+        );
+    }
+
+    #[rstest]
+    fn test_curl_with_ndjson() {
+        let input = r#"
+            [tasks.bulk]
+            POST = "https://example.com/_bulk"
+            body.ndjson = [{ a = 1 }, { b = 2 }]
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "bulk", "--curl"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "curl -X POST 'https://example.com/_bulk' \\\n\
+             \t-H 'content-type:application/x-ndjson' \\\n\
+             \t--data-binary @- << REQUEST_BODY\n\
+             {\"a\":1}\n\
+             {\"b\":2}\n\
+             REQUEST_BODY\n",
+        );
+    }
+
+    #[rstest]
+    #[case(&["req", "get", "--pager"], true, None, Some("more"))]
+    #[case(&["req", "get", "--pager"], false, None, None)]
+    #[case(&["req", "get", "--pager"], true, Some("1"), None)]
+    #[case(&["req", "get"], true, None, None)]
+    fn test_pager_command(
+        #[case] args: &[&str],
+        #[case] is_terminal: bool,
+        #[case] no_pager: Option<&str>,
+        #[case] expected: Option<&str>,
+    ) {
+        let _lock = ENV_LOCK.lock().unwrap();
+        std::env::set_var("PAGER", "more");
+        match no_pager {
+            Some(v) => std::env::set_var("NO_PAGER", v),
+            None => std::env::remove_var("NO_PAGER"),
+        }
+        let opt = Opt::try_parse_from(args).unwrap();
+
+        let pager = opt.pager_command(is_terminal);
+        std::env::remove_var("PAGER");
+        std::env::remove_var("NO_PAGER");
+
+        assert_eq!(pager.as_deref(), expected);
+    }
+
+    #[rstest]
+    fn test_pager_skipped_without_terminal(server: MockServer) {
         let input = format!(
             r#"
-                [tasks.connect]
-                CONNECT = "http://{}"
+                [tasks.get]
+                GET = "http://{}/get"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--pager"]).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
+            then.status(200).body("ok");
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        if !stdout().is_terminal() {
+            assert_eq!(out, b"ok");
+        }
+    }
+
+    #[rstest]
+    fn test_read_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nab");
+            std::thread::sleep(Duration::from_secs(3));
+        });
+        let input = format!(
+            r#"
+                [tasks.trickle]
+                GET = "http://{}/trickle"
+                config.read-timeout = "200ms"
+            "#,
+            addr,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "trickle"]).unwrap();
+
+        let start = Instant::now();
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(err.to_string(), "task `trickle` timed out");
+        assert!(
+            format!("{:#}", err).contains("no bytes of response body arrived for 200ms"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[rstest]
+    #[case(vec!["-v", "SERVICE=api"], "/api")]
+    #[case(vec![], "/web")]
+    fn test_interpolated_name(server: MockServer, #[case] vars: Vec<&str>, #[case] path: &str) {
+        let input = format!(
+            r#"
+                [variables]
+                SERVICE = "web"
+
+                [tasks.api-health]
+                GET = "http://{0}/api"
+
+                [tasks.web-health]
+                GET = "http://{0}/web"
+            "#,
+            server.address(),
+        );
+        let mut args = vec!["req", "-f", "-", "${SERVICE}-health"];
+        args.extend(vars);
+        let opt = Opt::try_parse_from(args).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path(path);
+            then.status(200).body("ok");
+        });
+
+        let code = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[rstest]
+    fn test_interpolated_name_undefined() {
+        let input = r#"
+            [tasks.api-health]
+            GET = "http://localhost/api"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "${SERVICE}-health"]).unwrap();
+
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", err),
+            "fail to resolve task name: value named \"SERVICE\" not defined"
+        );
+    }
+
+    #[rstest]
+    fn test_parallel_tasks(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.slow]
+                GET = "http://{0}/slow"
+
+                [tasks.missing]
+                GET = "http://{0}/missing"
+
+                [tasks.fast]
+                GET = "http://{0}/fast"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "slow",
+            "missing",
+            "fast",
+            "undefined",
+            "--parallel",
+            "4",
+        ])
+        .unwrap();
+        let slow_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/slow");
+            then.status(200)
+                .delay(Duration::from_millis(300))
+                .body("slow\n");
+        });
+        let missing_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/missing");
+            then.status(404).body("missing\n");
+        });
+        let fast_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/fast");
+            then.status(200).body("fast\n");
+        });
+        let mut out = vec![];
+
+        let start = Instant::now();
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert!(start.elapsed() < Duration::from_millis(600));
+        slow_mock.assert();
+        missing_mock.assert();
+        fast_mock.assert();
+        assert_eq!(code, ExitCode::FAILURE);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "==> slow <==\nslow\n\n==> missing <==\nmissing\n\n==> fast <==\nfast\n\n==> undefined <==\n"
+        );
+    }
+
+    #[rstest]
+    fn test_depends_and_capture(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.login]
+                POST = "http://{0}/login"
+                capture = {{ token = "$.data.token", id = "$.data.users[0].id" }}
+
+                [tasks.me]
+                GET = "http://{0}/users/${{id}}"
+                depends = ["login"]
+                headers.Authorization = "Bearer ${{token}}"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "me"]).unwrap();
+        let login_mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/login");
+            then.status(200)
+                .json_body(json!({ "data": { "token": "abc", "users": [{ "id": 42 }] } }));
+        });
+        let me_mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/users/42")
+                .header("Authorization", "Bearer abc");
+            then.status(200).body("me");
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        login_mock.assert();
+        me_mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out, b"me");
+    }
+
+    #[rstest]
+    fn test_depends_and_capture_header(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.login]
+                POST = "http://{0}/login"
+                capture = {{ session = "header:Set-Cookie" }}
+
+                [tasks.me]
+                GET = "http://{0}/me"
+                depends = ["login"]
+                headers.Cookie = "${{session}}"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "me"]).unwrap();
+        let login_mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/login");
+            then.status(204)
+                .header("Set-Cookie", "session=abc")
+                .header("Set-Cookie", "theme=dark");
+        });
+        let me_mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/me")
+                .header("Cookie", "session=abc");
+            then.status(200).body("me");
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        login_mock.assert();
+        me_mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out, b"me");
+    }
+
+    #[rstest]
+    #[case(401, "$.token", "responded 401 Unauthorized")]
+    #[case(200, "$.missing", "`$.missing` matches nothing to capture `token`")]
+    #[case(
+        200,
+        "header:X-Token",
+        "response header `X-Token` is missing to capture `token`"
+    )]
+    fn test_depends_failure(
+        server: MockServer,
+        #[case] status: u16,
+        #[case] path: &str,
+        #[case] message: &str,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.login]
+                POST = "http://{0}/login"
+                capture = {{ token = "{1}" }}
+
+                [tasks.me]
+                GET = "http://{0}/me"
+                depends = ["login"]
+                headers.Authorization = "Bearer ${{token}}"
+            "#,
+            server.address(),
+            path,
+        );
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "me"]).unwrap();
+        let login_mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/login");
+            then.status(status).json_body(json!({ "token": "abc" }));
+        });
+        let me_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/me");
+            then.status(200);
+        });
+
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
+
+        login_mock.assert();
+        me_mock.assert_hits(0);
+        assert!(format!("{:#}", err).starts_with("dependency `login` failed"));
+        assert!(format!("{:#}", err).contains(message), "{:#}", err);
+    }
+
+    #[rstest]
+    #[case(vec!["--capture", "token=.access_token"], "token=abc\n")]
+    #[case(
+        vec!["--capture", "name=$.user.name", "--capture", "id=.user.id"],
+        "id=42\nname='O'\\''Brien Jr.'\n"
+    )]
+    fn test_capture_option(server: MockServer, #[case] args: Vec<&str>, #[case] expected: &str) {
+        let input = format!(
+            r#"
+                [tasks.login]
+                POST = "http://{}/login"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from([vec!["req", "-f", "-", "login"], args].concat()).unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/login");
+            then.status(200).json_body(json!({
+                "access_token": "abc",
+                "user": { "id": 42, "name": "O'Brien Jr." },
+            }));
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(201, "201 42 abc\n", ExitCode::SUCCESS)]
+    #[case(404, "404 42 abc\n", ExitCode::FAILURE)]
+    fn test_template_option(
+        server: MockServer,
+        #[case] status: u16,
+        #[case] expected: &str,
+        #[case] expected_code: ExitCode,
+    ) {
+        let input = format!(
+            r#"
+                [tasks.create]
+                POST = "http://{}/create"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "create",
+            "--template",
+            "{{status}} {{json .id}} {{headers.x-request-id}}",
+        ])
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/create");
+            then.status(status)
+                .header("x-request-id", "abc")
+                .json_body(json!({ "id": 42 }));
+        });
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(code, expected_code);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn test_capture_option_missing(server: MockServer) {
+        let input = format!(
+            r#"
+                [tasks.login]
+                POST = "http://{}/login"
+            "#,
+            server.address(),
+        );
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "login",
+            "--capture",
+            "token=.missing",
+        ])
+        .unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/login");
+            then.status(200).json_body(json!({ "access_token": "abc" }));
+        });
+        let mut out = vec![];
+
+        let err = opt.exec(&mut input.as_bytes(), &mut out).unwrap_err();
+
+        mock.assert();
+        assert!(out.is_empty());
+        assert!(
+            format!("{:#}", err).contains("`$.missing` matches nothing to capture `token`"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[rstest]
+    #[case("text/plain", "line3\nline4\n")]
+    #[case("application/octet-stream", "line1\nline2\nline3\nline4\n")]
+    fn test_skip_lines(server: MockServer, #[case] content_type: &str, #[case] expected: &str) {
+        let input = format!(
+            r#"
+                [tasks.get]
+                GET = "http://{}/get"
             "#,
             server.address(),
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "connect"]).unwrap();
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--skip-lines", "2"]).unwrap();
         let mock = server.mock(|when, then| {
-            when.method(Method::CONNECT).path("");
-            then.status(200).body("ok");
+            when.method(Method::GET).path("/get");
+            then.status(200)
+                .header("content-type", content_type)
+                .body("line1\nline2\nline3\nline4\n");
         });
+        let mut out = vec![];
 
-        let code = opt
-            .exec(&mut input.as_bytes(), &mut std::io::empty())
-            .unwrap();
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
 
         mock.assert();
         assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_skip_lines_across_writes() {
+        let mut w = SkipLines::new(vec![], 2);
+        for chunk in ["li", "ne1\nline", "2\nli", "ne3\n"] {
+            w.write_all(chunk.as_bytes()).unwrap();
+        }
+        assert_eq!(w.inner, b"line3\n");
     }
 
     #[rstest]
-    fn test_get_with_queries(server: MockServer) {
+    fn test_cookies_across_dependencies(server: MockServer) {
+        let jar = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
         let input = format!(
             r#"
-                [tasks.get_with_queries]
-                GET = "http://{}/get_with_queries"
+                [config]
+                cookie-jar = "{}"
 
-                [tasks.get_with_queries.queries]
-                foo = "FOO"
-                bar = "BAR"
+                [tasks.login]
+                POST = "http://{1}/login"
+
+                [tasks.me]
+                GET = "http://{1}/me"
+                depends = ["login"]
             "#,
+            jar.display(),
             server.address(),
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get_with_queries"]).unwrap();
-        let mock = server.mock(|when, then| {
+        let login_mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/login");
+            then.status(200)
+                .header("set-cookie", "session=abc; Path=/; Max-Age=3600");
+        });
+        let me_mock = server.mock(|when, then| {
             when.method(Method::GET)
-                .path("/get_with_queries")
-                .query_param("foo", "FOO")
-                .query_param("bar", "BAR");
-            then.status(200).body("ok");
+                .path("/me")
+                .header("cookie", "session=abc");
+            then.status(200).body("me");
         });
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "me"]).unwrap();
+        let mut out = vec![];
 
-        let code = opt
-            .exec(&mut input.as_bytes(), &mut std::io::empty())
-            .unwrap();
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
 
-        mock.assert();
+        let saved = fs::read_to_string(&jar).unwrap();
+        fs::remove_file(&jar).unwrap();
+        login_mock.assert();
+        me_mock.assert();
         assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out, b"me");
+        let fields: Vec<&str> = saved.lines().nth(1).unwrap().split('\t').collect();
+        assert_eq!(fields[..4], ["127.0.0.1", "FALSE", "/", "FALSE"]);
+        assert_eq!(fields[5..], ["session", "abc"]);
     }
 
     #[rstest]
-    fn test_get_with_headers(server: MockServer) {
+    fn test_cookie_jar_loaded(server: MockServer) {
+        let jar = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::write(&jar, "127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\txyz\n").unwrap();
         let input = format!(
             r#"
-                [tasks.get_with_headers]
-                GET = "http://{}/get_with_headers"
-
-                [tasks.get_with_headers.headers]
-                "X-Authorization" = "Bearer HOGE"
+                [tasks.me]
+                GET = "http://{}/me"
+                config.cookie-jar = "{}"
             "#,
             server.address(),
+            jar.display(),
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get_with_headers"]).unwrap();
         let mock = server.mock(|when, then| {
             when.method(Method::GET)
-                .path("/get_with_headers")
-                .header("X-Authorization", "Bearer HOGE");
-            then.status(200).body("ok");
+                .path("/me")
+                .header("cookie", "session=xyz");
+            then.status(200);
         });
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "me"]).unwrap();
 
         let code = opt
             .exec(&mut input.as_bytes(), &mut std::io::empty())
             .unwrap();
 
+        fs::remove_file(&jar).unwrap();
         mock.assert();
         assert_eq!(code, ExitCode::SUCCESS);
     }
 
     #[rstest]
-    fn test_post_with_body(server: MockServer) {
+    #[case(
+        "application/json",
+        r#"{"a":[1,true],"b":{},"c":"x"}"#,
+        "{\n  \"a\": [\n    1,\n    true\n  ],\n  \"b\": {},\n  \"c\": \"x\"\n}\n"
+    )]
+    #[case("text/plain", r#"{"a":1}"#, r#"{"a":1}"#)]
+    #[case("application/json", "not json", "not json")]
+    fn test_pretty(
+        server: MockServer,
+        #[case] content_type: &str,
+        #[case] body: &str,
+        #[case] expected: &str,
+    ) {
         let input = format!(
             r#"
-                [tasks.post_with_body]
-                POST = "http://{}/post_with_body"
-
-                [tasks.post_with_body.body]
-                plain = "hello"
+                [tasks.get]
+                GET = "http://{}/get"
             "#,
             server.address(),
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_body"]).unwrap();
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get", "--pretty"]).unwrap();
         let mock = server.mock(|when, then| {
-            when.method(Method::POST)
-                .path("/post_with_body")
-                .body("hello");
-            then.status(200).body("ok");
+            when.method(Method::GET).path("/get");
+            then.status(200)
+                .header("content-type", content_type)
+                .body(body);
         });
+        let mut out = vec![];
 
-        let code = opt
-            .exec(&mut input.as_bytes(), &mut std::io::empty())
-            .unwrap();
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
 
         mock.assert();
         assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_pretty_json_colored() {
+        let mut out = String::new();
+        write_pretty_json(&json!({ "k": [null, 1, "s"] }), true, 0, &mut out);
+        assert_eq!(
+            out,
+            "{\n  \x1b[34;1m\"k\"\x1b[0m: [\n    \x1b[33mnull\x1b[0m,\n    \x1b[36m1\x1b[0m,\n    \x1b[32m\"s\"\x1b[0m\n  ]\n}"
+        );
     }
 
     #[rstest]
-    fn test_post_with_json(server: MockServer) {
+    fn test_setup(server: MockServer) {
         let input = format!(
             r#"
-                [tasks.post_with_json]
-                POST = "http://{}/post_with_json"
+                [[setup]]
+                POST = "http://{0}/token"
+                capture = {{ token = "$.token" }}
 
-                [tasks.post_with_json.body.json]
-                str = "hello"
-                num = 42
-                bool = true
-                obj = {{ "foo"="bar" }}
+                [[setup]]
+                GET = "http://{0}/tenant"
+                headers.Authorization = "Bearer ${{token}}"
+                capture = {{ tenant = "$.id" }}
+
+                [tasks.me]
+                GET = "http://{0}/${{tenant}}/me"
+                headers.Authorization = "Bearer ${{token}}"
             "#,
             server.address(),
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_json"]).unwrap();
-        let mock = server.mock(|when, then| {
-            when.method(Method::POST)
-                .path("/post_with_json")
-                .header("content-type", "application/json")
-                .json_body(json!({
-                    "str": "hello",
-                    "num": 42,
-                    "bool": true,
-                    "obj": { "foo": "bar" },
-                }));
-            then.status(200).body("ok");
+        let token_mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/token");
+            then.status(200).json_body(json!({ "token": "abc" }));
+        });
+        let tenant_mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/tenant")
+                .header("Authorization", "Bearer abc");
+            then.status(200).json_body(json!({ "id": "t1" }));
         });
+        let me_mock = server.mock(|when, then| {
+            when.method(Method::GET)
+                .path("/t1/me")
+                .header("Authorization", "Bearer abc");
+            then.status(200).body("me");
+        });
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "me"]).unwrap();
+        let mut out = vec![];
 
-        let code = opt
-            .exec(&mut input.as_bytes(), &mut std::io::empty())
-            .unwrap();
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
 
-        mock.assert();
+        token_mock.assert();
+        tenant_mock.assert();
+        me_mock.assert();
         assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out, b"me");
     }
 
     #[rstest]
-    fn test_post_with_form(server: MockServer) {
+    fn test_setup_failure(server: MockServer) {
         let input = format!(
             r#"
-                [tasks.post_with_form]
-                POST = "http://{}/post_with_form"
+                [[setup]]
+                POST = "http://{0}/token"
+                capture = {{ token = "$.token" }}
 
-                [tasks.post_with_form.body.form]
-                foo = "FOO"
-                bar = "BAR"
+                [tasks.me]
+                GET = "http://{0}/me"
             "#,
             server.address(),
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_form"]).unwrap();
-        let mock = server.mock(|when, then| {
-            when.method(Method::POST)
-                .path("/post_with_form")
-                .header("content-type", "application/x-www-form-urlencoded")
-                .x_www_form_urlencoded_tuple("foo", "FOO")
-                .x_www_form_urlencoded_tuple("bar", "BAR");
-            then.status(200).body("ok");
+        let token_mock = server.mock(|when, then| {
+            when.method(Method::POST).path("/token");
+            then.status(500);
         });
+        let me_mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/me");
+            then.status(200);
+        });
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "me"]).unwrap();
 
-        let code = opt
+        let err = opt
             .exec(&mut input.as_bytes(), &mut std::io::empty())
-            .unwrap();
+            .unwrap_err();
 
-        mock.assert();
-        assert_eq!(code, ExitCode::SUCCESS);
+        token_mock.assert();
+        me_mock.assert_hits(0);
+        assert_eq!(
+            format!("{:#}", err),
+            "setup step 1 failed: responded 500 Internal Server Error"
+        );
     }
 
     #[rstest]
-    fn test_post_with_multipart(server: MockServer) {
-        let uuid = Uuid::new_v4();
-
+    #[case(&[], true)]
+    #[case(&["--no-create-dirs"], false)]
+    fn test_output_creates_dirs(server: MockServer, #[case] args: &[&str], #[case] created: bool) {
+        let root = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        let path = root.join("reports/2024/out.json");
         let input = format!(
             r#"
-                [tasks.post_with_multipart]
-                POST = "http://{}/post_with_multipart"
-
-                [tasks.post_with_multipart.body.multipart]
-                uuid = "{}"
-                foo = "FOO"
+                [tasks.get]
+                GET = "http://{}/get"
             "#,
             server.address(),
-            uuid,
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_multipart"]).unwrap();
+        let mut argv = vec!["req", "-f", "-", "get", "--out", path.to_str().unwrap()];
+        argv.extend(args);
+        let opt = Opt::try_parse_from(argv).unwrap();
         let mock = server.mock(|when, then| {
-            when.method(Method::POST)
-                .path("/post_with_multipart")
-                .body_contains(uuid.to_string());
-            then.status(200).body("ok");
+            when.method(Method::GET).path("/get");
+            then.status(200).body("{}");
         });
-        let code = opt
-            .exec(&mut input.as_bytes(), &mut std::io::empty())
-            .unwrap();
+
+        let res = opt.exec(&mut input.as_bytes(), &mut std::io::empty());
+
         mock.assert();
-        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(res.is_ok(), created);
+        assert_eq!(path.exists(), created);
+        if created {
+            assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+            fs::remove_dir_all(&root).unwrap();
+        }
     }
 
     #[rstest]
-    fn test_post_with_file(server: MockServer) {
+    fn test_delay(server: MockServer) {
         let input = format!(
             r#"
-                [tasks.post_with_multipart]
-                POST = "http://{}/post_with_multipart"
+                [variables]
+                DELAY = "200ms"
 
-                [tasks.post_with_multipart.body.multipart]
-                "Cargo.toml".file = "Cargo.toml"
+                [tasks.get]
+                GET = "http://{}/get"
+
+                [config]
+                delay = "${{DELAY}}"
+                jitter = "100ms"
             "#,
             server.address(),
         );
-        let content = fs::read("Cargo.toml").unwrap();
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post_with_multipart"]).unwrap();
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
         let mock = server.mock(|when, then| {
-            when.method(Method::POST)
-                .path("/post_with_multipart")
-                .body_contains(String::from_utf8(content).unwrap());
+            when.method(Method::GET).path("/get");
             then.status(200).body("ok");
         });
-        
+
+        let start = std::time::Instant::now();
         let code = opt
             .exec(&mut input.as_bytes(), &mut std::io::empty())
             .unwrap();
+        let elapsed = start.elapsed();
 
         mock.assert();
         assert_eq!(code, ExitCode::SUCCESS);
+        assert!(
+            elapsed >= std::time::Duration::from_millis(200),
+            "elapsed: {:?}",
+            elapsed
+        );
     }
 
     #[rstest]
-    fn test_redirect(server: MockServer) {
+    fn test_rate_limit(server: MockServer) {
+        let path = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::write(&path, "corrupt").unwrap();
         let input = format!(
             r#"
-                [tasks.redirect]
-                GET = "http://{}/redirect/0"
+                [tasks.get]
+                GET = "http://{}/get"
 
                 [config]
-                redirect = 2
+                rate-limit = "2/s"
+                rate-state-file = "{}"
             "#,
             server.address(),
+            path.display(),
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "redirect"]).unwrap();
-        let mock_first = server.mock(|when, then| {
-            when.method(Method::GET)
-                .path("/redirect/0");
-            then.status(302).header("Location", server.url("/redirect/1"));
-        });
-        let mock_second = server.mock(|when, then| {
-            when.method(Method::GET)
-                .path("/redirect/1");
+        let mock = server.mock(|when, then| {
+            when.method(Method::GET).path("/get");
             then.status(200).body("ok");
         });
+        let invoke = || {
+            let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
+            let start = std::time::Instant::now();
+            let code = opt
+                .exec(&mut input.as_bytes(), &mut std::io::empty())
+                .unwrap();
+            assert_eq!(code, ExitCode::SUCCESS);
+            start.elapsed()
+        };
 
-        let code = opt
-            .exec(&mut input.as_bytes(), &mut std::io::empty())
-            .unwrap();
+        let first = invoke();
+        let second = invoke();
+        fs::remove_file(&path).unwrap();
 
-        mock_first.assert();
-        mock_second.assert();
-        assert_eq!(code, ExitCode::SUCCESS);
+        mock.assert_hits(2);
+        assert!(
+            first < std::time::Duration::from_millis(500),
+            "first: {:?}",
+            first
+        );
+        assert!(
+            second >= std::time::Duration::from_millis(400),
+            "second: {:?}",
+            second
+        );
     }
 
     #[rstest]
-    fn test_too_many_redirect(server: MockServer) {
+    #[case("rate-limit = \"fast\"", "invalid rate-limit: fast")]
+    #[case("rate-limit = \"0/s\"", "invalid rate-limit: 0/s")]
+    #[case(
+        "rate-limit = \"1/s\"",
+        "config.rate-limit requires config.rate-state-file"
+    )]
+    fn test_rate_limit_error(#[case] config: &str, #[case] expected: &str) {
         let input = format!(
-            r#"
-                [tasks.redirect]
-                GET = "http://{}/redirect/0"
-
-                [config]
-                redirect = 2
-            "#,
-            server.address(),
+            "[tasks.get]\nGET = \"http://127.0.0.1:1/get\"\n[config]\n{}",
+            config
         );
-        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "redirect"]).unwrap();
-        let mock_first = server.mock(|when, then| {
-            when.method(Method::GET)
-                .path("/redirect/0");
-            then.status(302).header("Location", server.url("/redirect/1"));
-        });
-        let mock_second = server.mock(|when, then| {
-            when.method(Method::GET)
-                .path("/redirect/1");
-            then.status(302).header("Location", server.url("/redirect/2"));
-        });
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "get"]).unwrap();
 
-        let res = opt
-            .exec(&mut input.as_bytes(), &mut std::io::empty());
+        let err = opt
+            .exec(&mut input.as_bytes(), &mut std::io::empty())
+            .unwrap_err();
 
-        mock_first.assert();
-        mock_second.assert();
-        assert!(res.is_err(), "result: {:#?}", res);
+        assert!(format!("{:#}", err).contains(expected), "{:#}", err);
+    }
+
+    #[rstest]
+    #[case(
+        "[tasks.foo]\nGET = \"https://example.com\"\n[tasks.bar]\nPOST = \"https://example.com\"",
+        "bar\nfoo\n"
+    )]
+    #[case("[tasks.foo", "")]
+    fn test_complete_tasks(#[case] input: &str, #[case] expected: &str) {
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "--complete-tasks"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
     }
 
     #[rstest]
@@ -610,4 +6207,210 @@ mod tests {
 
         assert_eq!(code, ExitCode::SUCCESS);
     }
+
+    #[rstest]
+    fn test_body_shorthand() {
+        let dryrun = |body: &str| {
+            let input = format!(
+                r#"
+                    variables.NAME = "req"
+                    [tasks.post]
+                    POST = "https://example.com/post"
+                    {}
+                "#,
+                body,
+            );
+            let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post", "--dryrun=http"]).unwrap();
+            let mut out = vec![];
+            let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+            assert_eq!(code, ExitCode::SUCCESS);
+            String::from_utf8(out).unwrap()
+        };
+
+        let shorthand = dryrun(r#"body = "hello ${NAME}""#);
+
+        assert!(shorthand.ends_with("\nhello req"), "{}", shorthand);
+        assert_eq!(shorthand, dryrun(r#"body.plain = "hello ${NAME}""#));
+    }
+
+    #[rstest]
+    fn test_explain() {
+        let dir = std::env::temp_dir().join(format!("req-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(".env"), "HOST=example.com\n").unwrap();
+        fs::write(
+            dir.join("common.toml"),
+            "[variables]\nVERSION = \"v2\"\n\n[headers]\nAccept = \"application/json\"\n",
+        )
+        .unwrap();
+        let path = dir.join("req.toml");
+        fs::write(
+            &path,
+            r#"
+                include = ["common.toml"]
+
+                [variables]
+                PREFIX = "api"
+
+                [config]
+                env-file = ".env"
+
+                [tasks.get]
+                GET = "https://${HOST}/${PREFIX}/${VERSION}/users?token=${TOKEN}&q=${Q:-all}"
+            "#,
+        )
+        .unwrap();
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            path.to_str().unwrap(),
+            "get",
+            "--explain",
+            "-v",
+            "TOKEN=secret",
+        ])
+        .unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut std::io::empty(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        let out = String::from_utf8(out).unwrap();
+        let lines = [
+            format!("read definitions from {}", path.display()),
+            format!("included {}", dir.join("common.toml").display()),
+            format!(
+                "variable `HOST` = \"example.com\" from env file {}",
+                dir.join(".env").display()
+            ),
+            "variable `PREFIX` = \"api\" from variables".to_string(),
+            "variable `TOKEN` = \"secret\" from -v".to_string(),
+            "config from global config".to_string(),
+            "header `Accept` from top-level headers".to_string(),
+            "value named \"Q\" not defined, default \"all\" is used".to_string(),
+            "GET /api/v2/users?token=secret&q=all HTTP/1.1".to_string(),
+        ];
+        for line in lines.iter() {
+            assert!(
+                out.lines().any(|l| l == line),
+                "{:?} not in:\n{}",
+                line,
+                out
+            );
+        }
+        assert!(out.contains(&format!(
+            "variable `VERSION` = \"v2\" from variables of {}",
+            dir.join("common.toml").display()
+        )));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rstest]
+    fn test_dryrun_http() {
+        let input = r#"
+            [tasks.post]
+            POST = "http://example.com:8080/post"
+            queries.q = "query"
+            headers.x-foo = "FOO"
+            auth.bearer = "secret"
+            body.json = { foo = "FOO" }
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "post", "--dryrun=http"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                "POST /post?q=query HTTP/1.1",
+                "host: example.com:8080",
+                concat!("user-agent: req/", env!("CARGO_PKG_VERSION")),
+                "content-type: application/json",
+                "x-foo: FOO",
+                "authorization: Bearer secret",
+                "accept: */*",
+                "content-length: 13",
+                "",
+                r#"{"foo":"FOO"}"#,
+            ]
+            .join("\r\n"),
+        );
+    }
+
+    #[rstest]
+    fn test_list_json() {
+        let input = r#"
+            [config]
+            default-method = "HEAD"
+
+            [tasks.get]
+            GET = "https://${HOST}/get"
+            description = "GET request"
+
+            [tasks.health]
+            url = "https://${HOST}/health"
+        "#;
+        let opt = Opt::try_parse_from(vec!["req", "-f", "-", "--list-json"]).unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&out).unwrap(),
+            json!([
+                {
+                    "name": "get",
+                    "description": "GET request",
+                    "method": "GET",
+                    "url": "https://${HOST}/get",
+                },
+                {
+                    "name": "health",
+                    "description": "",
+                    "method": "HEAD",
+                    "url": "https://${HOST}/health",
+                },
+            ])
+        );
+    }
+
+    #[rstest]
+    fn test_list_vars_used() {
+        let input = r#"
+            [variables]
+            HOST = "example.com"
+            PATH = "${UNDEFINED}/path"
+
+            [tasks.vars]
+            GET = "https://${HOST}/${PATH}"
+
+            [tasks.vars.headers]
+            Authorization = "Bearer ${TOKEN}"
+
+            [tasks.vars.queries]
+            q = "$QUERY $${ESCAPED}"
+        "#;
+        let opt = Opt::try_parse_from(vec![
+            "req",
+            "-f",
+            "-",
+            "vars",
+            "--list-vars-used",
+            "-v",
+            "QUERY=foo",
+        ])
+        .unwrap();
+        let mut out = vec![];
+
+        let code = opt.exec(&mut input.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HOST\tresolved\nPATH\tunresolved\nQUERY\tresolved\nTOKEN\tunresolved\n",
+        );
+    }
 }
@@ -0,0 +1,165 @@
+use anyhow::{anyhow, Context};
+use base64::Engine;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+const PREFIX: &str = "sha256//";
+
+#[derive(Debug)]
+pub struct PinnedCertVerifier {
+    fingerprints: Vec<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertVerifier {
+    pub fn new(pins: &[String]) -> anyhow::Result<Self> {
+        let fingerprints = pins
+            .iter()
+            .map(|pin| {
+                let digest = pin
+                    .strip_prefix(PREFIX)
+                    .ok_or_else(|| anyhow!("expected sha256//BASE64 in pinned-certs: {}", pin))?;
+                base64::engine::general_purpose::STANDARD
+                    .decode(digest)
+                    .ok()
+                    .and_then(|d| <[u8; 32]>::try_from(d).ok())
+                    .ok_or_else(|| anyhow!("invalid SHA-256 digest in pinned-certs: {}", pin))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(PinnedCertVerifier {
+            fingerprints,
+            provider: Arc::new(rustls::crypto::ring::default_provider()),
+        })
+    }
+
+    pub fn into_tls_config(self, alpn: &[&[u8]]) -> anyhow::Result<rustls::ClientConfig> {
+        let mut config = rustls::ClientConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .context("fail to configure TLS")?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(self))
+            .with_no_client_auth();
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        Ok(config)
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let cert = webpki::EndEntityCert::try_from(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        let spki = cert.subject_public_key_info();
+        let digest: [u8; 32] = Sha256::digest(spki.as_ref()).into();
+        if self.fingerprints.contains(&digest) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "public key {}{} is not pinned",
+                PREFIX,
+                base64::engine::general_purpose::STANDARD.encode(digest)
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verify(verifier: &PinnedCertVerifier, cert: &[u8]) -> Result<(), rustls::Error> {
+        verifier
+            .verify_server_cert(
+                &CertificateDer::from(cert),
+                &[],
+                &ServerName::try_from("example.com").unwrap(),
+                &[],
+                UnixTime::now(),
+            )
+            .map(|_| ())
+    }
+
+    const CERT: &str = "MIIBfTCCASOgAwIBAgIUTZfr6jC/VJIbNnUilcKDo+5bC7EwCgYIKoZIzj0EAwIwEzERMA8GA1UEAwwIcmVxLXRlc3QwIBcNMjYxMDE2MTEwMzAyWhgPMjEyNjA5MjIxMTAzMDJaMBMxETAPBgNVBAMMCHJlcS10ZXN0MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEOpRgm7antG7A8dz+gg5GEWlDqFhNC4BgdWg7GdtfSY2XZiHgolXaPgaBgJxV9hER65Hs94cGhqF/LZ7nROpxUqNTMFEwHQYDVR0OBBYEFJDdOo0K8Q0dieUvbIJPF/DwzaM+MB8GA1UdIwQYMBaAFJDdOo0K8Q0dieUvbIJPF/DwzaM+MA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIhAOiOgmp1yx/YOPhwINDV8bu4RrJvNvkWmwfKH0qEiAMsAiBXBnBlqItNoBvnX5Iy4Cl5ZGKBVbh9bVdhIn2HOWKihw==";
+
+    #[test]
+    fn test_pinned_cert_verifier() {
+        let cert = base64::engine::general_purpose::STANDARD
+            .decode(CERT)
+            .unwrap();
+        let pinned =
+            PinnedCertVerifier::new(&["sha256//iP8xdtbGbrS8ZGOLAoLNbslIos9nZvh6yGjLQ3kDY4A=".into()])
+                .unwrap();
+        let other = PinnedCertVerifier::new(&[format!(
+            "sha256//{}",
+            base64::engine::general_purpose::STANDARD.encode(Sha256::digest(&cert))
+        )])
+        .unwrap();
+
+        assert!(verify(&pinned, &cert).is_ok());
+        let err = verify(&other, &cert).unwrap_err();
+        assert!(err.to_string().contains("is not pinned"), "{}", err);
+        let err = verify(&pinned, b"not a certificate").unwrap_err();
+        assert!(err.to_string().contains("invalid peer certificate"), "{}", err);
+    }
+
+    #[test]
+    fn test_pinned_cert_verifier_invalid_pin() {
+        assert_eq!(
+            PinnedCertVerifier::new(&["abc".into()])
+                .unwrap_err()
+                .to_string(),
+            "expected sha256//BASE64 in pinned-certs: abc"
+        );
+        assert_eq!(
+            PinnedCertVerifier::new(&["sha256//YWJj".into()])
+                .unwrap_err()
+                .to_string(),
+            "invalid SHA-256 digest in pinned-certs: sha256//YWJj"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, Read};
+use std::sync::{Arc, OnceLock};
+
+#[derive(Debug, Clone)]
+pub struct Upload {
+    total: u64,
+    visible: bool,
+    bar: Arc<OnceLock<ProgressBar>>,
+}
+
+impl Upload {
+    pub fn new(total: u64, visible: bool) -> Self {
+        Upload {
+            total,
+            visible,
+            bar: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn reader<R: Read>(&self, inner: R) -> UploadReader<R> {
+        UploadReader {
+            inner,
+            upload: self.clone(),
+        }
+    }
+
+    // the bar is drawn from the first read, so building a request which is never sent shows nothing
+    fn bar(&self) -> &ProgressBar {
+        self.bar.get_or_init(|| {
+            if !self.visible {
+                return ProgressBar::hidden();
+            }
+            let style = ProgressStyle::default_bar()
+                .template(
+                    "{spinner:.cyan} [{elapsed_precise}] [{bar:.cyan}] {bytes}/{total_bytes} ({bytes_per_sec})",
+                )
+                .expect("template is valid")
+                .progress_chars("||.");
+            ProgressBar::new(self.total).with_style(style)
+        })
+    }
+}
+
+pub struct UploadReader<R> {
+    inner: R,
+    upload: Upload,
+}
+
+impl<R: Read> Read for UploadReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let pb = self.upload.bar();
+        pb.inc(n as u64);
+        if pb.position() >= self.upload.total {
+            pb.abandon();
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_counts_bytes_of_parts() {
+        let upload = Upload::new(11, true);
+        let mut first = upload.reader(&b"hello"[..]);
+        let mut second = upload.reader(&b" world"[..]);
+        assert!(upload.bar.get().is_none());
+
+        let mut out = String::new();
+        first.read_to_string(&mut out).unwrap();
+        assert_eq!(upload.bar().position(), 5);
+        second.read_to_string(&mut out).unwrap();
+        assert_eq!(upload.bar().position(), 11);
+        assert_eq!(out, "hello world");
+    }
+
+    #[test]
+    fn test_upload_hidden() {
+        let upload = Upload::new(5, false);
+        let mut out = String::new();
+        upload
+            .reader(&b"hello"[..])
+            .read_to_string(&mut out)
+            .unwrap();
+        assert!(upload.bar().is_hidden());
+        assert_eq!(upload.bar().position(), 5);
+        assert_eq!(out, "hello");
+    }
+}
@@ -0,0 +1,113 @@
+use crate::data::lookup_json_path;
+use anyhow::anyhow;
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::sync::LazyLock;
+
+static EXPR_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{(.*?)\}\}").unwrap());
+
+pub fn render(
+    template: &str,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+    strict: bool,
+) -> anyhow::Result<String> {
+    let mut json = None;
+    let mut out = String::new();
+    let mut last = 0;
+    for cap in EXPR_PATTERN.captures_iter(template) {
+        let all = cap.get(0).unwrap();
+        out.push_str(&template[last..all.start()]);
+        last = all.end();
+        let expr = cap[1].trim();
+        let value = if expr == "status" {
+            Some(status.as_u16().to_string())
+        } else if let Some(name) = expr.strip_prefix("headers.") {
+            headers
+                .get(name)
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+        } else if let Some(path) = expr.strip_prefix("json ") {
+            let path = path.trim();
+            let path = match path.strip_prefix('.') {
+                Some(_) => format!("${}", path),
+                None => path.to_string(),
+            };
+            let json = json.get_or_insert_with(|| serde_json::from_slice::<Value>(body).ok());
+            json.as_ref()
+                .and_then(|json| lookup_json_path(json, &path))
+                .map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                })
+        } else {
+            return Err(anyhow!("unknown expression in template: {{{{{}}}}}", expr));
+        };
+        match value {
+            Some(value) => out.push_str(&value),
+            None if strict => return Err(anyhow!("`{}` matches nothing in template", expr)),
+            None => {}
+        }
+    }
+    out.push_str(&template[last..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, CONTENT_TYPE};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("{{status}} {{json .id}}", false, Ok("200 42"))]
+    #[case(
+        "{{ json $.user.name }} ({{headers.content-type}})",
+        false,
+        Ok("alice (application/json)")
+    )]
+    #[case("{{json .tags}}", false, Ok(r#"["a","b"]"#))]
+    #[case("[{{json .missing}}] [{{headers.x-missing}}]", false, Ok("[] []"))]
+    #[case(
+        "{{json .missing}}",
+        true,
+        Err("`json .missing` matches nothing in template")
+    )]
+    #[case(
+        "{{headers.x-missing}}",
+        true,
+        Err("`headers.x-missing` matches nothing in template")
+    )]
+    #[case("{{body}}", false, Err("unknown expression in template: {{body}}"))]
+    fn test_render(
+        #[case] template: &str,
+        #[case] strict: bool,
+        #[case] expected: Result<&str, &str>,
+    ) {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let body = br#"{"id": 42, "user": {"name": "alice"}, "tags": ["a", "b"]}"#;
+
+        let res = render(template, StatusCode::OK, &headers, body, strict);
+
+        match expected {
+            Ok(expected) => assert_eq!(res.unwrap(), expected),
+            Err(expected) => assert_eq!(res.unwrap_err().to_string(), expected),
+        }
+    }
+
+    #[test]
+    fn test_render_non_json_body() {
+        let res = render(
+            "{{status}}:{{json .id}}",
+            StatusCode::NOT_FOUND,
+            &HeaderMap::new(),
+            b"not found",
+            false,
+        );
+
+        assert_eq!(res.unwrap(), "404:");
+    }
+}